@@ -0,0 +1,136 @@
+// Image read and write benchmarks.
+//
+// Compares the original per pixel algorithm, which converted a whole
+// pixel for each bit, with reading and writing the image sample buffer
+// directly, on one thread and on as many as there are cores.
+// Run with: cargo bench --no-default-features
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use image::{DynamicImage, GenericImage, GenericImageView, Pixel, RgbImage};
+
+use hidey::settings::Settings;
+use hidey::steg::Steganography;
+
+const WIDTH: u32 = 1000;
+const HEIGHT: u32 = 1000;
+const PAYLOAD_LEN: usize = 256 * 1024;
+
+// Image with every sample different, so that nothing is optimised away.
+fn test_image() -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        image::Rgb([(x * 7 + y) as u8, (x + y * 13) as u8, (x * y) as u8])
+    }))
+}
+
+fn payload() -> Vec<u8> {
+    (0..PAYLOAD_LEN).map(|idx| (idx * 31 + idx / 7) as u8).collect()
+}
+
+// Steganography instance with the test image loaded, reading and
+// writing in parallel for chunks of at least par_threshold bytes.
+fn loaded_steg(par_threshold: u32) -> Steganography {
+    let settings = Settings { par_threshold, ..Settings::default() };
+    let mut steg = Steganography::new(settings);
+    steg.image = Some(test_image());
+    steg.pic_width = WIDTH;
+    steg.pic_height = HEIGHT;
+    steg.pic_col_planes = 3;
+    steg.img_to_proc = true;
+    steg
+}
+
+// Original write algorithm, getting and putting a whole pixel for each bit.
+fn write_per_pixel(image: &mut DynamicImage, bytes: &[u8]) {
+    let (width, height) = image.dimensions();
+    let (mut row, mut col, mut plane, mut bit) = (0u32, 0u32, 0usize, 0u8);
+    for byte in bytes {
+        for shift in (0..8).rev() {
+            let mut rgb = image.get_pixel(col, row).to_rgb();
+            rgb[plane] = (rgb[plane] & !(1 << bit)) | (((byte >> shift) & 1) << bit);
+            image.put_pixel(col, row, image::Rgba([rgb[0], rgb[1], rgb[2], 255]));
+            col += 1;
+            if col == width {
+                col = 0;
+                row += 1;
+                if row == height {
+                    row = 0;
+                    plane += 1;
+                    if plane == 3 {
+                        plane = 0;
+                        bit += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Original read algorithm, converting a whole pixel for each bit.
+fn read_per_pixel(image: &DynamicImage, out: &mut [u8]) {
+    let (width, height) = image.dimensions();
+    let (mut row, mut col, mut plane, mut bit) = (0u32, 0u32, 0usize, 0u8);
+    for byte in out.iter_mut() {
+        let mut data = 0u8;
+        for _ in 0..8 {
+            data = (data << 1) | ((image.get_pixel(col, row).to_rgb()[plane] >> bit) & 1);
+            col += 1;
+            if col == width {
+                col = 0;
+                row += 1;
+                if row == height {
+                    row = 0;
+                    plane += 1;
+                    if plane == 3 {
+                        plane = 0;
+                        bit += 1;
+                    }
+                }
+            }
+        }
+        *byte = data;
+    }
+}
+
+fn bench_write(c: &mut Criterion) {
+    let bytes = payload();
+    let mut group = c.benchmark_group("write");
+    group.throughput(Throughput::Bytes(PAYLOAD_LEN as u64));
+    group.sample_size(10);
+
+    group.bench_function("per_pixel", |b| {
+        b.iter_batched_ref(test_image, |image| write_per_pixel(image, black_box(&bytes)), BatchSize::LargeInput)
+    });
+    for (name, par_threshold) in [("sample_buffer", u32::MAX), ("sample_buffer_par", 0)] {
+        group.bench_function(name, |b| {
+            b.iter_batched_ref(
+                || loaded_steg(par_threshold),
+                |steg| steg.write_data_to_image(black_box(&bytes)),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_read(c: &mut Criterion) {
+    let mut out = vec![0u8; PAYLOAD_LEN];
+    let mut group = c.benchmark_group("read");
+    group.throughput(Throughput::Bytes(PAYLOAD_LEN as u64));
+    group.sample_size(10);
+
+    let image = test_image();
+    group.bench_function("per_pixel", |b| b.iter(|| read_per_pixel(black_box(&image), &mut out)));
+    for (name, par_threshold) in [("sample_buffer", u32::MAX), ("sample_buffer_par", 0)] {
+        let mut steg = loaded_steg(par_threshold);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                steg.set_bit_index(0);
+                steg.read_data_into(black_box(&mut out))
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_write, bench_read);
+criterion_main!(benches);
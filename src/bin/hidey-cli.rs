@@ -0,0 +1,406 @@
+// Steganography command line application.
+//
+// Embeds files in, and extracts files from, lossless images without
+// needing a display, so that hidey can be used in scripts and on servers.
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::io::{self, BufWriter, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use zeroize::Zeroizing;
+
+use hidey::password::{Password, PasswordSource};
+use hidey::settings::Settings;
+use hidey::steg::batch::{summary_table, BatchAction};
+use hidey::steg::embed::PayloadEntry;
+use hidey::steg::options::{Compression, EmbedOptions, EmbedOptionsBuilder, Encryption, FecLevel, OutputFormat, Traversal};
+use hidey::steg::report::ImageReport;
+use hidey::steg::{StegError, Steganography};
+
+// Settings file used if none is given, when there is one.
+const DEFAULT_SETTINGS_FILE: &str = "settings.yml";
+
+#[derive(Parser)]
+#[command(name = "hidey-cli", version, about = "Hide files in lossless images, and get them back")]
+struct Cli {
+    /// Settings file [default: settings.yml, if there is one]
+    #[arg(long, global = true)]
+    settings: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Embed files into an image
+    Embed {
+        /// Image to embed into
+        image: String,
+        /// Files to embed, or - for stdin [default: stdin]
+        files: Vec<String>,
+        /// Name to embed stdin as
+        #[arg(long, default_value = "stdin")]
+        name: String,
+        #[command(flatten)]
+        password: PasswordArgs,
+        /// Image file to save [default: overwrite the image]
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Image format to save as, png, bmp or tiff [default: from the image file extension]
+        #[arg(long)]
+        format: Option<String>,
+        #[command(flatten)]
+        options: OptionArgs,
+    },
+    /// Extract the embedded files from an image
+    Extract {
+        /// Image to extract from
+        image: String,
+        #[command(flatten)]
+        password: PasswordArgs,
+        /// Folder to extract into [default: the settings secret folder]
+        #[arg(short, long, conflicts_with = "stdout")]
+        output: Option<String>,
+        /// Write one embedded file to stdout, instead of to a folder
+        #[arg(long)]
+        stdout: bool,
+        /// Embedded file to write to stdout [default: the first embedded file]
+        #[arg(long, requires = "stdout")]
+        entry: Option<String>,
+    },
+    /// List the embedded files in an image
+    List {
+        /// Image to list
+        image: String,
+        #[command(flatten)]
+        password: PasswordArgs,
+    },
+    /// Report on an image, and what is embedded in it
+    Info {
+        /// Image to report on
+        image: String,
+        #[command(flatten)]
+        password: PasswordArgs,
+        /// Report format
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+    },
+    /// Show how much an image can hold, and whether files would fit
+    Capacity {
+        /// Image to check
+        image: String,
+        /// Files that would be embedded
+        files: Vec<String>,
+        #[command(flatten)]
+        password: PasswordArgs,
+        #[command(flatten)]
+        options: OptionArgs,
+    },
+    /// Check, or extract from, every image in a folder
+    Batch {
+        /// Folder of images [default: the settings image folder]
+        folder: Option<String>,
+        /// Extract the embedded files from each image, into a folder per image
+        #[arg(long)]
+        extract: bool,
+        #[command(flatten)]
+        password: PasswordArgs,
+        /// Folder to extract into [default: the settings secret folder]
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+}
+
+// Where to get the password from.
+// Only one source can be given.
+#[derive(Args)]
+#[group(multiple = false)]
+struct PasswordArgs {
+    /// Password (visible to other users, prefer the other password options)
+    #[arg(short, long)]
+    password: Option<String>,
+    /// Prompt for the password, without echoing it
+    #[arg(long)]
+    ask_password: bool,
+    /// Environment variable holding the password
+    #[arg(long, value_name = "VAR")]
+    password_env: Option<String>,
+    /// File whose first line is the password
+    #[arg(long, value_name = "FILE")]
+    password_file: Option<PathBuf>,
+    /// Inherited file descriptor, 3 or more, to read the password from
+    #[arg(long, value_name = "FD")]
+    password_fd: Option<i32>,
+}
+
+impl PasswordArgs {
+    // Read the password from whichever source was given, if any.
+    // Prompted passwords have to be confirmed when embedding.
+    fn read(&mut self, confirm: bool) -> Result<Option<Password>, StegError> {
+        if let Some(password) = self.password.take() {
+            return Ok(Some(Zeroizing::new(password)));
+        }
+        let source = if self.ask_password {
+            PasswordSource::Prompt
+        }
+        else if let Some(var) = &self.password_env {
+            PasswordSource::Env(var.clone())
+        }
+        else if let Some(file) = &self.password_file {
+            PasswordSource::File(file.clone())
+        }
+        else if let Some(fd) = self.password_fd {
+            PasswordSource::Fd(fd)
+        }
+        else {
+            return Ok(None);
+        };
+        Ok(Some(source.read(confirm)?))
+    }
+
+    // Password for a loaded image, prompting for it if the image
+    // has a password, no source was given, and there is a terminal.
+    fn read_for_image(&mut self, steg: &Steganography) -> Result<Password, StegError> {
+        match self.read(false)? {
+            Some(password) => Ok(password),
+            None if steg.pic_has_pw && io::stderr().is_terminal() => Ok(PasswordSource::Prompt.read(false)?),
+            None => Ok(Zeroizing::new(String::new())),
+        }
+    }
+}
+
+// Embedding options, other than the password.
+#[derive(Args)]
+struct OptionArgs {
+    /// Encrypt the embedded files with the password
+    #[arg(long)]
+    encrypt: bool,
+    /// Compress the embedded files
+    #[arg(long)]
+    compress: bool,
+    /// Scatter the embedded data over the image, instead of filling it from the top
+    #[arg(long)]
+    permute: bool,
+    /// Copies of the embedded data, for error correction (1, 3 or 5)
+    #[arg(long, default_value_t = 1)]
+    fec: u8,
+    /// Lowest bits of each colour to use, 1 to 8 [default: from the settings]
+    #[arg(long)]
+    bits: Option<u8>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportFormat {
+    Text,
+    Json,
+    Yaml,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("hidey-cli: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode, StegError> {
+    let settings = load_settings(cli.settings.as_deref())?;
+
+    match cli.command {
+        Command::Embed { image, files, name, mut password, output, format, options } => {
+            // Check where, and how, the image will be saved before embedding.
+            let output = output.unwrap_or_else(|| image.clone());
+            let output_format = match format {
+                Some(format) => OutputFormat::from_extension(&format)?,
+                None => OutputFormat::from_path(&output)?,
+            };
+            let password = password.read(true)?;
+            let options = embed_options(&settings, password.as_deref().map(String::as_str), &options, Some(output_format))?;
+
+            let mut steg = load_image(settings, &image)?;
+            let entries = payload_entries(&files, &name)?;
+            let num_files = entries.len();
+            steg.embed(entries, &options)?;
+            steg.save_image(&output)?;
+            println!("Embedded {} file(s) into: {}", num_files, output);
+        }
+        Command::Extract { image, mut password, output, stdout, entry } => {
+            let mut steg = load_image(settings, &image)?;
+            let password = password.read_for_image(&steg)?;
+            if stdout {
+                let mut writer = BufWriter::new(io::stdout().lock());
+                let metadata = steg.extract_entry_to(&password, entry.as_deref(), &mut writer)?;
+                eprintln!("Wrote {} bytes to stdout", metadata.size);
+                return Ok(ExitCode::SUCCESS);
+            }
+            if let Some(folder) = output {
+                steg.settings.secret_folder = folder;
+            }
+            steg.extract_data(password.to_string())?;
+            println!("Extracted files to: {}", steg.settings.secret_folder);
+        }
+        Command::List { image, mut password } => {
+            let mut steg = load_image(settings, &image)?;
+            if !steg.pic_coded {
+                return Err(StegError::NotCoded);
+            }
+            let password = password.read_for_image(&steg)?;
+            let report = steg.image_report(Some(&password))?;
+            for entry in report.entries.unwrap_or_default() {
+                println!("{:>12}  {}", entry.size, entry.name);
+            }
+        }
+        Command::Info { image, mut password, format } => {
+            let mut steg = load_image(settings, &image)?;
+            let password = password.read(false)?;
+            let report = steg.image_report(password.as_deref().map(String::as_str))?;
+            match format {
+                ReportFormat::Text => print_report(&report),
+                ReportFormat::Json => println!("{}", report.to_json()?),
+                ReportFormat::Yaml => print!("{}", report.to_yaml()?),
+            }
+        }
+        Command::Capacity { image, files, mut password, options } => {
+            let password = password.read(false)?;
+            let options = embed_options(&settings, password.as_deref().map(String::as_str), &options, None)?;
+            let steg = load_image(settings, &image)?;
+            let available = steg.usable_capacity(&options);
+            println!("Usable capacity: {} bytes", available);
+            println!("Header overhead: {} bytes", steg.header_bytes(&options));
+            if !files.is_empty() {
+                let entries = payload_entries(&files, "stdin")?;
+                let required = steg.required_bytes(&entries, &options);
+                println!("Required: {} bytes", required);
+                if required > available {
+                    return Err(StegError::CapacityExceeded { required, available });
+                }
+                println!("Files fit, with {} bytes to spare", available - required);
+            }
+        }
+        Command::Batch { folder, extract, mut password, output } => {
+            let password = password.read(false)?.unwrap_or_default();
+            let mut steg = Steganography::new(settings);
+            if let Some(output) = output {
+                steg.settings.secret_folder = output;
+            }
+            let action = if extract { BatchAction::Extract } else { BatchAction::Report };
+            let results = steg.batch_folder(folder.as_deref(), action, &password)?;
+            print!("{}", summary_table(&results));
+
+            // Still a failure for scripts if any image failed.
+            if results.iter().any(|result| result.error.is_some()) {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// Settings from the settings file given, or the default settings file
+// if there is one, or else the default settings.
+fn load_settings(settings_file: Option<&str>) -> Result<Settings, StegError> {
+    match settings_file {
+        Some(file) => Ok(Settings::from_file(file)?),
+        None if Path::new(DEFAULT_SETTINGS_FILE).exists() => Ok(Settings::from_file(DEFAULT_SETTINGS_FILE)?),
+        None => Ok(Settings::default()),
+    }
+}
+
+// Entries for the files to embed, where - is stdin, given at most once.
+// With no files, stdin is embedded, as long as it isn't a terminal.
+fn payload_entries(files: &[String], stdin_name: &str) -> Result<Vec<PayloadEntry<'static>>, StegError> {
+    if files.is_empty() {
+        if io::stdin().is_terminal() {
+            return Err(StegError::InvalidPayload(String::from("No files to embed, and nothing piped to stdin")));
+        }
+        return Ok(vec![PayloadEntry::from_reader(stdin_name, io::stdin().lock())?]);
+    }
+    // Stdin can only be read once.
+    if files.iter().filter(|file| *file == "-").count() > 1 {
+        return Err(StegError::InvalidPayload(String::from("Stdin (-) can only be given once as a file to embed")));
+    }
+    let mut entries = Vec::with_capacity(files.len());
+    for file in files {
+        if file == "-" {
+            entries.push(PayloadEntry::from_reader(stdin_name, io::stdin().lock())?);
+        }
+        else {
+            entries.push(PayloadEntry::from_path(file)?);
+        }
+    }
+    Ok(entries)
+}
+
+// Load an image given on the command line.
+// Images are loaded relative to the current folder,
+// not the settings image folder.
+fn load_image(settings: Settings, image: &str) -> Result<Steganography, StegError> {
+    let mut steg = Steganography::new(settings);
+    steg.load_image_file(Path::new(image))?;
+    Ok(steg)
+}
+
+fn embed_options(
+    settings: &Settings,
+    password: Option<&str>,
+    args: &OptionArgs,
+    output_format: Option<OutputFormat>,
+) -> Result<EmbedOptions, StegError> {
+    let fec_level = FecLevel::from_copies(args.fec)
+        .ok_or_else(|| StegError::InvalidOptions(format!("Error correction copies must be 1, 3 or 5, not {}", args.fec)))?;
+    let mut builder = EmbedOptionsBuilder::from_settings(settings).fec_level(fec_level);
+    if let Some(output_format) = output_format {
+        builder = builder.output_format(output_format);
+    }
+    if args.permute {
+        builder = builder.traversal(Traversal::Permuted);
+    }
+    if let Some(password) = password {
+        builder = builder.password(password);
+    }
+    if args.encrypt {
+        builder = builder.encryption(Encryption::ChaCha20Poly1305);
+    }
+    if args.compress {
+        builder = builder.compression(Compression::Deflate);
+    }
+    if let Some(bits) = args.bits {
+        builder = builder.bits_per_channel(bits);
+    }
+    builder.build()
+}
+
+fn print_report(report: &ImageReport) {
+    println!("Image:           {}", report.image_file);
+    println!("Dimensions:      {} x {}", report.width, report.height);
+    println!("Colour type:     {} ({} planes)", report.colour_type, report.colour_planes);
+    println!("Usable capacity: {} bytes", report.usable_capacity);
+    println!("Header overhead: {} bytes", report.header_overhead);
+    println!("Pic coded:       {}", if report.coded { "yes" } else { "no" });
+    if let Some(container) = &report.container {
+        println!("Container:       version {}", container.version);
+        println!("Password:        {}", if container.password { "yes" } else { "no" });
+        println!("Encryption:      {}", container.encryption);
+        println!("Compression:     {}", container.compression);
+        println!("Traversal:       {}", container.traversal);
+        println!("Error copies:    {}", container.fec_copies);
+        if let Some(kdf) = &container.kdf {
+            println!("Key derivation:  {}, {} iterations, salt {}", kdf.algorithm, kdf.iterations, kdf.salt);
+        }
+    }
+    match &report.entries {
+        Some(entries) => {
+            println!("Embedded files:  {}", entries.len());
+            for entry in entries {
+                println!("  {:>12}  {}", entry.size, entry.name);
+            }
+        }
+        None if report.coded => println!("Embedded files:  password needed to list"),
+        None => {}
+    }
+}
@@ -0,0 +1,9 @@
+// Steganography library.
+//
+// Embeds files in, and extracts files from, lossless images.
+// Settings are passed in by the caller, so the library doesn't
+// depend on the GUI, logging set up, or the current directory.
+
+pub mod password;
+pub mod settings;
+pub mod steg;
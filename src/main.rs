@@ -1,38 +1,32 @@
 // Steganography application.
 
-use log::info;
-use log4rs;
+use log::{info, warn};
 use gtk::prelude::*;
-use lazy_static::lazy_static;
 use std::cell::RefCell;
 use std::rc::Rc;
-use std::fs::File;
-use std::io::prelude::*;
-use std::sync::Mutex;
-use serde_yaml;
 
-use crate::settings::Settings;
-use crate::steg::Steganography;
+use hidey::settings::Settings;
+use hidey::steg::Steganography;
 use crate::ui::{on_activate, on_startup};
 
-pub mod settings;
-pub mod steg;
 mod ui;
 
-// Create a global variable for applications settings.
-// This was available in other files.
-lazy_static! {
-    static ref SETTINGS: Mutex<Settings> = {
-        // Read YAML settings file.
-        let mut file = File::open("settings.yml").expect("Unable to open file");
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).expect("Unable to read file");
-
-        // Deserialize YAML into Settings struct.
-        let settings: Settings = serde_yaml::from_str(&contents).expect("Unable to parse YAML");
-        info!("Application settings loaded, version: {}", settings.settings_version);
-        Mutex::new(settings)
-    };
+// Settings file, read from the current folder.
+const SETTINGS_FILE: &str = "settings.yml";
+
+// Load the application settings, using the default settings
+// if the settings file is missing or can't be read.
+fn load_settings() -> Settings {
+    match Settings::from_file(SETTINGS_FILE) {
+        Ok(settings) => {
+            info!("Application settings loaded, version: {}", settings.settings_version);
+            settings
+        }
+        Err(err) => {
+            warn!("Unable to load settings file: {}, {}, using default settings", SETTINGS_FILE, err);
+            Settings::default()
+        }
+    }
 }
 
 // Steganoraphy mainline.
@@ -44,10 +38,10 @@ fn main() {
     // Get application metadata to include in initial logging.
     info!("Application started, version: {}", env!("CARGO_PKG_VERSION"));
 
-    // Instatiate a steganography struct.
+    // Instatiate a steganography struct with the application settings.
     // Make interior shareable as need to pass to UI
     // menu servicing functions.
-    let img_steg = Rc::new(RefCell::new(Steganography::init()));
+    let img_steg = Rc::new(RefCell::new(Steganography::new(load_settings())));
 
     // Create a new GTK application.
     let application = gtk::Application::builder()
@@ -0,0 +1,199 @@
+// Password input sources.
+//
+// Passwords can be typed at a prompt without being echoed, or read
+// from an environment variable, a password file, or a file descriptor
+// inherited from the parent process, so that they never have to
+// appear on a command line.
+// Passwords are held in buffers that are zeroed when dropped.
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use zeroize::Zeroizing;
+
+// Password, zeroed when dropped.
+pub type Password = Zeroizing<String>;
+
+// Where to get a password from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PasswordSource {
+    // Prompt on the terminal, without echoing the password.
+    Prompt,
+    // Environment variable holding the password.
+    Env(String),
+    // File whose first line is the password.
+    File(PathBuf),
+    // Inherited file descriptor, whose first line is the password.
+    Fd(i32),
+}
+
+impl PasswordSource {
+    // Read the password from the source.
+    // When confirming, a prompted password has to be typed twice,
+    // so that a mistyped password isn't used for embedding.
+    pub fn read(&self, confirm: bool) -> io::Result<Password> {
+        match self {
+            PasswordSource::Prompt => {
+                let password = Zeroizing::new(rpassword::prompt_password("Password: ")?);
+                if confirm {
+                    let again = Zeroizing::new(rpassword::prompt_password("Confirm password: ")?);
+                    if *again != *password {
+                        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Passwords do not match"));
+                    }
+                }
+                Ok(password)
+            }
+            PasswordSource::Env(var) => match env::var(var) {
+                Ok(password) => Ok(Zeroizing::new(password)),
+                Err(_) => Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Password environment variable not set: {}", var),
+                )),
+            },
+            PasswordSource::File(path) => read_first_line(File::open(path)?),
+            PasswordSource::Fd(fd) => read_fd(*fd),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn read_fd(fd: i32) -> io::Result<Password> {
+    use std::os::fd::BorrowedFd;
+
+    // Stdin, stdout and stderr are never password descriptors,
+    // and must not be read from, or closed, here.
+    if fd <= 2 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid password file descriptor: {}", fd)));
+    }
+    // SAFETY: the descriptor was handed to this process to read the
+    // password from. It is only borrowed long enough to duplicate it,
+    // so that the duplicate is read and closed, not the descriptor itself.
+    // Duplicating a descriptor that isn't open fails, rather than
+    // taking ownership of anything.
+    let file = File::from(unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned()?);
+    read_first_line(file)
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: i32) -> io::Result<Password> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Password file descriptors are only supported on unix"))
+}
+
+// Longest password that is read from a file or file descriptor.
+const MAX_PASSWORD_LEN: usize = 1024;
+
+// First line of a source, without its line ending.
+// The line is read a byte at a time, into a buffer that is never
+// reallocated, so that no copy of the password is left behind
+// unzeroed, and nothing past the first line is read.
+fn read_first_line(mut source: impl Read) -> io::Result<Password> {
+    let mut line = Zeroizing::new(Vec::with_capacity(MAX_PASSWORD_LEN));
+    let mut byte = Zeroizing::new([0u8; 1]);
+    loop {
+        match source.read(&mut byte[..]) {
+            Ok(0) => break,
+            Ok(_) if byte[0] == b'\n' => break,
+            Ok(_) if line.len() == MAX_PASSWORD_LEN => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Password longer than {} bytes", MAX_PASSWORD_LEN),
+                ));
+            }
+            Ok(_) => line.push(byte[0]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    let line = line.strip_suffix(b"\r").unwrap_or(&line);
+    match std::str::from_utf8(line) {
+        Ok(line) => {
+            let mut password = Zeroizing::new(String::with_capacity(line.len()));
+            password.push_str(line);
+            Ok(password)
+        }
+        Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "Password is not valid UTF-8")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::steg::testing::test_folder;
+
+    use std::fs;
+
+    #[test]
+    fn env_password_is_whole_value() {
+        env::set_var("HIDEY_TEST_PASSWORD", "secret pass");
+        let password = PasswordSource::Env(String::from("HIDEY_TEST_PASSWORD")).read(false).unwrap();
+        assert_eq!(password.as_str(), "secret pass");
+
+        let result = PasswordSource::Env(String::from("HIDEY_TEST_PASSWORD_UNSET")).read(false);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    // Only the first line of a file is the password,
+    // without a unix or windows line ending.
+    #[test]
+    fn file_password_is_first_line() {
+        let folder = test_folder("password-file");
+        let cases:[(&[u8], &str); 6] = [
+            (b"secret", "secret"),
+            (b"secret\n", "secret"),
+            (b"secret\r\n", "secret"),
+            (b"secret\nsecond line\n", "secret"),
+            (b"\nsecond line", ""),
+            (b" spaced out \r\nsecond", " spaced out "),
+        ];
+        for (contents, expected) in cases {
+            let path = folder.join("password.txt");
+            fs::write(&path, contents).unwrap();
+            assert_eq!(PasswordSource::File(path).read(false).unwrap().as_str(), expected);
+        }
+        // Passwords that are too long, or not text, are rejected.
+        for contents in [vec![b'x'; MAX_PASSWORD_LEN + 1], vec![0xff, 0xfe]] {
+            let path = folder.join("password.txt");
+            fs::write(&path, contents).unwrap();
+            assert_eq!(PasswordSource::File(path).read(false).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+        let path = folder.join("password.txt");
+        fs::write(&path, [b'x'; MAX_PASSWORD_LEN]).unwrap();
+        assert_eq!(PasswordSource::File(path).read(false).unwrap().len(), MAX_PASSWORD_LEN);
+
+        let missing = PasswordSource::File(folder.join("missing.txt")).read(false);
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    // Stdin, stdout and stderr are never read as password descriptors.
+    #[cfg(unix)]
+    #[test]
+    fn standard_descriptors_are_rejected() {
+        for fd in [-1, 0, 1, 2] {
+            let result = PasswordSource::Fd(fd).read(false);
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput, "fd: {}", fd);
+        }
+    }
+
+    // The descriptor is read through a duplicate, and is left open.
+    #[cfg(unix)]
+    #[test]
+    fn fd_password_is_first_line() {
+        use std::io::{Seek, SeekFrom};
+        use std::os::fd::AsRawFd;
+
+        let folder = test_folder("password-fd");
+        let path = folder.join("password.txt");
+        fs::write(&path, b"secret\r\nsecond line").unwrap();
+        let mut file = File::open(&path).unwrap();
+        let password = PasswordSource::Fd(file.as_raw_fd()).read(false).unwrap();
+        assert_eq!(password.as_str(), "secret");
+
+        let mut contents = String::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "secret\r\nsecond line");
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -1,14 +1,50 @@
 use serde::{Deserialize};
+use std::fs;
+use std::io;
 
+// Application settings.
+// Any settings missing from a settings file take their default value.
 #[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
 pub struct Settings {
     pub settings_version: String,
     pub max_embed_ratio: f32,
     pub min_capacity: u64,
     pub prog_code: String,
     pub byte_chunk: u32,
+    pub par_threshold: u32,
+    // Most bytes that compressed embedded data can decompress to.
+    pub max_decompressed_len: u64,
     pub secret_folder: String,
     pub thumb_folder: String,
     pub window_width: i32,
     pub window_height: i32,
 }
+
+// Default settings, the same as the settings file
+// shipped with the application.
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            settings_version: String::from("0.1.0"),
+            max_embed_ratio: 0.5,
+            min_capacity: 1000,
+            prog_code: String::from("PICCODER"),
+            byte_chunk: 50000,
+            par_threshold: 16384,
+            max_decompressed_len: 268_435_456,
+            secret_folder: String::from("./secret"),
+            thumb_folder: String::from("./images"),
+            window_width: 1000,
+            window_height: 700,
+        }
+    }
+}
+
+// Load settings from a YAML settings file.
+impl Settings {
+    pub fn from_file(path: &str) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        serde_yaml::from_str(&contents).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
@@ -0,0 +1,213 @@
+// Batch processing methods.
+//
+// Every image in a folder can be checked for embedded files, or have
+// its embedded files extracted, in one go.
+// A failure with one image is recorded against that image, and the
+// rest of the images are still processed.
+
+use crate::steg::extract::{EntryMetadata, ExtractSink, FolderSink};
+use crate::steg::options::OutputFormat;
+use crate::steg::{StegError, Steganography};
+
+use log::{info, warn};
+use serde::Serialize;
+use std::fmt::Write;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// What to do with each image in a folder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchAction {
+    // Report whether each image is pic coded, and has a password.
+    Report,
+    // Extract the embedded files from each pic coded image,
+    // into a folder for each image.
+    Extract,
+}
+
+// Outcome for one image in a folder.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResult {
+    pub image: String,
+    pub coded: bool,
+    pub password: bool,
+    // Number of embedded files, if they could be read.
+    pub files: Option<usize>,
+    // Why the image couldn't be processed, if it couldn't.
+    pub error: Option<String>,
+}
+
+// Method to process all the images in a folder.
+// The folder defaults to the settings image folder, and files are
+// extracted to a subfolder of the settings secret folder, named
+// after each image.
+impl Steganography {
+    pub fn batch_folder(&mut self, folder:Option<&str>, action:BatchAction, pw:&str) -> Result<Vec<BatchResult>, StegError> {
+        let folder = folder.map(str::to_string).unwrap_or_else(|| self.settings.thumb_folder.clone());
+        info!("Batch processing folder: {}", folder);
+
+        // Only lossless images can have embedded files.
+        // Folder entries that can't be read are recorded as failures,
+        // without a name, as there isn't one to give.
+        let mut images:Vec<PathBuf> = Vec::new();
+        let mut unreadable:Vec<BatchResult> = Vec::new();
+        for dir_entry in fs::read_dir(&folder)? {
+            let image_path = match dir_entry {
+                Ok(dir_entry) => dir_entry.path(),
+                Err(err) => {
+                    warn!("Unable to read entry in folder: {}, {}", folder, err);
+                    unreadable.push(BatchResult {
+                        image: String::from("?"),
+                        coded: false,
+                        password: false,
+                        files: None,
+                        error: Some(StegError::Io(err).to_string()),
+                    });
+                    continue;
+                }
+            };
+            if image_path.is_file() && OutputFormat::from_path(&image_path.to_string_lossy()).is_ok() {
+                images.push(image_path);
+            }
+        }
+        images.sort();
+
+        let mut results = Vec::with_capacity(images.len());
+        for image_path in images {
+            self.progress.check_cancelled()?;
+            let image = image_path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            let mut result = BatchResult { image, coded: false, password: false, files: None, error: None };
+            if let Err(err) = self.batch_image(&image_path, action, pw, &mut result) {
+                warn!("Batch processing failed for: {}, {}", result.image, err);
+                result.error = Some(err.to_string());
+            }
+            results.push(result);
+        }
+        results.append(&mut unreadable);
+        Ok(results)
+    }
+}
+
+// Method to process one image of a batch, filling in its result.
+impl Steganography {
+    fn batch_image(&mut self, image_path:&Path, action:BatchAction, pw:&str, result:&mut BatchResult) -> Result<(), StegError> {
+        self.load_image_file(image_path)?;
+        result.coded = self.pic_coded;
+        result.password = self.pic_has_pw;
+        if !self.pic_coded {
+            return Ok(());
+        }
+
+        match action {
+            BatchAction::Report => {
+                // Embedded files can only be counted without a password.
+                // They were counted from the embedded header when the image
+                // was loaded, and are counted again if that failed, to find out why.
+                if !self.pic_has_pw {
+                    result.files = match self.pic_file_count {
+                        Some(files) => Some(files as usize),
+                        None => self.count_embedded_files()?.map(|files| files as usize),
+                    };
+                }
+            }
+            BatchAction::Extract => {
+                let stem = image_path.file_stem().unwrap_or_default();
+                let mut folder_sink = FolderSink { folder: Path::new(&self.settings.secret_folder).join(stem), written: Vec::new() };
+                let mut files = 0;
+                let mut sink = |name: &str, metadata: &EntryMetadata, data: &mut dyn Read| {
+                    files += 1;
+                    folder_sink.extract_entry(name, metadata, data)
+                };
+                self.extract_to_sink(pw, &mut sink)?;
+                result.files = Some(files);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Table summarising the results of a batch, one image per row.
+pub fn summary_table(results:&[BatchResult]) -> String {
+    let name_width = results.iter().map(|result| result.image.len()).max().unwrap_or(0).max("IMAGE".len());
+    let yes_no = |flag:bool| if flag { "yes" } else { "no" };
+
+    let mut table = String::new();
+    let _ = writeln!(table, "{:<name_width$}  CODED  PASSWORD  FILES  STATUS", "IMAGE");
+    for result in results {
+        let files = result.files.map(|files| files.to_string()).unwrap_or_else(|| String::from("-"));
+        let status = match (&result.error, result.coded) {
+            (Some(error), _) => error.as_str(),
+            (None, true) => "ok",
+            (None, false) => "not coded",
+        };
+        let password = if result.coded { yes_no(result.password) } else { "-" };
+        let _ = writeln!(
+            table,
+            "{:<name_width$}  {:<5}  {:<8}  {:>5}  {}",
+            result.image,
+            yes_no(result.coded),
+            password,
+            files,
+            status
+        );
+    }
+    let failed = results.iter().filter(|result| result.error.is_some()).count();
+    let coded = results.iter().filter(|result| result.coded).count();
+    let _ = writeln!(table, "{} image(s), {} coded, {} failed", results.len(), coded, failed);
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+    use crate::steg::embed::PayloadEntry;
+    use crate::steg::options::EmbedOptions;
+    use crate::steg::testing::{loaded_steg, test_folder};
+
+    // Folder with a pic coded image, an image that isn't pic coded,
+    // an image that can't be read, and a file that isn't an image.
+    fn batch_test_folder(name:&str) -> PathBuf {
+        let folder = test_folder(name);
+        let mut steg = loaded_steg(60, 60);
+        steg.save_image(&folder.join("plain.png").to_string_lossy()).unwrap();
+        let entries = vec![PayloadEntry::from_bytes("a.txt", b"hello"), PayloadEntry::from_bytes("b.txt", b"there")];
+        steg.embed(entries, &EmbedOptions::from_settings(&steg.settings)).unwrap();
+        steg.save_image(&folder.join("coded.png").to_string_lossy()).unwrap();
+        fs::write(folder.join("broken.png"), b"not really a png").unwrap();
+        fs::write(folder.join("notes.txt"), b"not an image").unwrap();
+        folder
+    }
+
+    // An image that can't be read is recorded as a failure,
+    // and the rest of the images are still processed.
+    #[test]
+    fn batch_carries_on_past_unreadable_image() {
+        let folder = batch_test_folder("batch-report");
+        let mut steg = Steganography::new(Settings::default());
+        let results = steg.batch_folder(Some(&folder.to_string_lossy()), BatchAction::Report, "").unwrap();
+
+        let images:Vec<&str> = results.iter().map(|result| result.image.as_str()).collect();
+        assert_eq!(images, ["broken.png", "coded.png", "plain.png"]);
+        assert!(results[0].error.is_some());
+        assert!(!results[0].coded);
+        assert_eq!((results[1].coded, results[1].files, &results[1].error), (true, Some(2), &None));
+        assert_eq!((results[2].coded, results[2].files, &results[2].error), (false, None, &None));
+        assert!(summary_table(&results).ends_with("3 image(s), 1 coded, 1 failed\n"));
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn batch_extracts_past_unreadable_image() {
+        let folder = batch_test_folder("batch-extract");
+        let mut steg = Steganography::new(Settings::default());
+        steg.settings.secret_folder = folder.join("secret").to_string_lossy().into_owned();
+        let results = steg.batch_folder(Some(&folder.to_string_lossy()), BatchAction::Extract, "").unwrap();
+
+        assert!(results[0].error.is_some());
+        assert_eq!(results[1].files, Some(2));
+        assert_eq!(fs::read(folder.join("secret").join("coded").join("b.txt")).unwrap(), b"there");
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
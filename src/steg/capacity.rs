@@ -0,0 +1,126 @@
+// Embedding capacity methods.
+//
+// The exact number of bytes an embedding needs, including all of the
+// header and file section overhead, versus the exact number of bytes
+// the image can hold with the embedding options.
+// Sizes are calculated in u64 so that they can't overflow for any
+// image size, or any number of files.
+
+use crate::steg::container::{self, CONTAINER_HEADER_LEN, DATA_LEN_DIGITS, KDF_PARAMS_LEN, TAG_LEN, TRAVERSAL_SEED_LEN};
+use crate::steg::embed::PayloadEntry;
+use crate::steg::options::{EmbedOptions, Encryption, Traversal};
+use crate::steg::Steganography;
+
+// Bytes of the file section for an entry: the file name length,
+// file name, file length and file contents.
+pub fn entry_bytes(entry:&PayloadEntry) -> u64 {
+    (3 + entry.name.len() as u64 + 10).saturating_add(entry.len)
+}
+
+// Bytes of the number of files, and the file sections,
+// before any compression or encryption.
+pub fn entries_bytes(entries:&[PayloadEntry]) -> u64 {
+    entries.iter().fold(3, |total:u64, entry| total.saturating_add(entry_bytes(entry)))
+}
+
+impl Steganography {
+    // Bytes that can be embedded in the loaded image with the options.
+    // Only the rgb colour planes are used, even for rgba images,
+    // and only the lowest bits per channel of each colour.
+    pub fn usable_capacity(&self, options:&EmbedOptions) -> u64 {
+        self.pic_pixels().saturating_mul(3 * options.bits_per_channel as u64) / 8
+    }
+
+    // Bytes of the header before the embedded files:
+    // the pic code, and the password or container header.
+    pub fn header_bytes(&self, options:&EmbedOptions) -> u64 {
+        let code_len = self.settings.prog_code.len() as u64;
+        let has_password = options.password.is_some();
+        if options.needs_container() {
+            let kdf_len = if has_password { KDF_PARAMS_LEN } else { 0 };
+            let seed_len = if options.traversal == Traversal::Permuted { TRAVERSAL_SEED_LEN } else { 0 };
+            code_len + CONTAINER_HEADER_LEN + kdf_len + seed_len + DATA_LEN_DIGITS
+        }
+        else {
+            let hash_len = if has_password { 32 } else { 0 };
+            code_len + 1 + hash_len
+        }
+    }
+
+    // Bytes needed to embed the entries with the options.
+    // With compression this is the most that can be needed, as the
+    // exact size is only known once the data has been compressed.
+    pub fn required_bytes(&self, entries:&[PayloadEntry], options:&EmbedOptions) -> u64 {
+        let mut data_len = entries_bytes(entries);
+        if options.needs_container() {
+            if options.encryption != Encryption::None {
+                data_len = data_len.saturating_add(TAG_LEN);
+            }
+            data_len = container::fec_len(data_len, options.fec_level.copies());
+        }
+        self.header_bytes(options).saturating_add(data_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::steg::options::FecLevel;
+    use crate::steg::testing::loaded_steg;
+    use crate::steg::StegError;
+
+    use std::io::{Read, Write};
+
+    const FILLER:u8 = 0xa5;
+
+    // The bytes needed are exactly the bytes written to the image.
+    // The image is filled with a known byte first, and the last
+    // byte of the embedded files is different, so the end of the
+    // embedding can be found.
+    #[test]
+    fn required_bytes_are_bytes_written() {
+        let contents:Vec<u8> = (0..999u32).map(|idx| (idx as u8) | 1).chain([0]).collect();
+        let options = [
+            EmbedOptions::builder().build().unwrap(),
+            EmbedOptions::builder().password("secret").build().unwrap(),
+            EmbedOptions::builder().fec_level(FecLevel::Repeat3).build().unwrap(),
+            EmbedOptions::builder().password("secret").fec_level(FecLevel::Repeat5).kdf_iterations(1000).build().unwrap(),
+        ];
+        for options in options {
+            let mut steg = loaded_steg(100, 100);
+            let capacity = (steg.bit_capacity() / 8) as usize;
+            steg.set_bit_index(0);
+            steg.writer().write_all(&vec![FILLER; capacity]).unwrap();
+
+            let entries = vec![PayloadEntry::from_bytes("a.txt", b"hello"), PayloadEntry::from_bytes("b.bin", &contents)];
+            let required = steg.required_bytes(&entries, &options) as usize;
+            steg.embed(entries, &options).unwrap();
+
+            let mut written = vec![0u8; capacity];
+            steg.set_bit_index(0);
+            steg.reader().read_exact(&mut written).unwrap();
+            assert_eq!(written[required - 1], 0, "{:?}", options);
+            assert!(written[required..].iter().all(|&byte| byte == FILLER), "{:?}", options);
+        }
+    }
+
+    // An embedding one byte larger than the capacity is short by exactly
+    // one byte, and one that is exactly the capacity fits.
+    #[test]
+    fn capacity_exceeded_by_one_byte() {
+        let options = EmbedOptions::builder().build().unwrap();
+        let mut steg = loaded_steg(50, 50);
+        let available = steg.usable_capacity(&options);
+        let overhead = steg.required_bytes(&[PayloadEntry::from_bytes("a.bin", b"")], &options);
+        let contents = vec![1u8; (available - overhead + 1) as usize];
+
+        let entries = vec![PayloadEntry::from_bytes("a.bin", &contents)];
+        assert_eq!(steg.required_bytes(&entries, &options), available + 1);
+        let err = steg.embed(entries, &options).unwrap_err();
+        assert!(matches!(err, StegError::CapacityExceeded { required, available: reported } if required == available + 1 && reported == available));
+        assert!(err.to_string().ends_with("short by: 1 bytes"), "{}", err);
+
+        steg.embed(vec![PayloadEntry::from_bytes("a.bin", &contents[1..])], &options).unwrap();
+        assert_eq!(steg.extract_to_memory("").unwrap()[0].bytes, &contents[1..]);
+    }
+}
@@ -0,0 +1,517 @@
+// Versioned container methods.
+//
+// The versioned container is used when embedding needs encryption,
+// compression or error correction, none of which the original
+// pic coded format can describe.
+// The format of the container, following the pic coded signature, is:
+//
+// Container marker : 1 byte, 'V' (the original format has 'Y' or 'N' here).
+// Container version : 1 digit.
+// Flags : 1 byte, bit 0 password, bit 1 encrypted, bit 2 compressed,
+//         bit 3 permuted traversal.
+// Error correction : 1 digit, number of copies of each block of data.
+// If password enabled : 16 byte salt, 10 digit PBKDF2 iterations,
+//                       32 byte password verifier.
+// If permuted traversal : 8 byte traversal seed.
+// Data length : 10 digit integer, leading zeros.
+// Data : data in blocks, each block repeated for error correction,
+//        with its bits in the order of the traversal.
+//
+// Before compression and encryption the data is the number of files
+// and the file sections, exactly as in the original format.
+
+use crate::steg::traversal::{Permutation, SEED_LEN};
+use crate::steg::{StegError, Steganography};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use log::{error, info};
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{constant_time, pbkdf2};
+use std::io::{Read, Write};
+use std::num::NonZeroU32;
+use zeroize::Zeroizing;
+
+pub const CONTAINER_MARKER: u8 = b'V';
+pub const CONTAINER_VERSION: u8 = 2;
+pub const FLAG_PASSWORD: u8 = 0x01;
+pub const FLAG_ENCRYPTED: u8 = 0x02;
+pub const FLAG_COMPRESSED: u8 = 0x04;
+pub const FLAG_PERMUTED: u8 = 0x08;
+const FLAGS_KNOWN: u8 = FLAG_PASSWORD | FLAG_ENCRYPTED | FLAG_COMPRESSED | FLAG_PERMUTED;
+pub const SALT_LEN: usize = 16;
+pub const VERIFIER_LEN: usize = 32;
+pub const KEY_LEN: usize = 32;
+
+// Encryption key, zeroed when dropped.
+pub type Key = Zeroizing<[u8; KEY_LEN]>;
+pub const TAG_LEN: u64 = 16;
+
+// Sizes of the container header fields, in bytes.
+// Marker, version, flags and error correction.
+pub const CONTAINER_HEADER_LEN: u64 = 4;
+// Salt, iterations and verifier.
+pub const KDF_PARAMS_LEN: u64 = SALT_LEN as u64 + 10 + VERIFIER_LEN as u64;
+pub const DATA_LEN_DIGITS: u64 = 10;
+// Seed of the permuted traversal.
+pub const TRAVERSAL_SEED_LEN: u64 = SEED_LEN as u64;
+
+// Size of the blocks that are repeated for error correction.
+// Fixed, so that it doesn't depend on the settings used to embed.
+pub const FEC_BLOCK: usize = 4096;
+
+// Key derivation parameters for a password.
+#[derive(Debug, Clone)]
+pub struct KdfParams {
+    pub salt: Vec<u8>,
+    pub iterations: u32,
+    pub verifier: Vec<u8>,
+}
+
+// Details of how the data in a pic coded image is stored,
+// read from the header.
+// Images in the original pic coded format are version 1.
+#[derive(Debug, Clone, Default)]
+pub struct ContainerHeader {
+    pub version: u8,
+    pub has_password: bool,
+    pub encrypted: bool,
+    pub compressed: bool,
+    pub fec_copies: u8,
+    pub kdf: Option<KdfParams>,
+    // Seed of the permuted traversal, if the data is permuted.
+    pub traversal_seed: Option<u64>,
+}
+
+impl ContainerHeader {
+    // Header for the original pic coded format.
+    pub fn original(has_password: bool) -> Self {
+        ContainerHeader {
+            version: 1,
+            has_password,
+            encrypted: false,
+            compressed: false,
+            fec_copies: 1,
+            kdf: None,
+            traversal_seed: None,
+        }
+    }
+}
+
+// Derive an encryption key, and a separate password verifier,
+// from a password.
+pub fn derive_key(password: &str, salt: &[u8], iterations: u32) -> Result<(Key, [u8; VERIFIER_LEN]), StegError> {
+    let iterations = NonZeroU32::new(iterations)
+        .ok_or_else(|| StegError::CorruptHeader(String::from("Invalid key derivation iterations")))?;
+    let mut derived = Zeroizing::new([0u8; KEY_LEN + VERIFIER_LEN]);
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, password.as_bytes(), &mut derived[..]);
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    let mut verifier = [0u8; VERIFIER_LEN];
+    key.copy_from_slice(&derived[..KEY_LEN]);
+    verifier.copy_from_slice(&derived[KEY_LEN..]);
+    Ok((key, verifier))
+}
+
+// New random salt for deriving a key.
+pub fn new_salt() -> Result<Vec<u8>, StegError> {
+    let mut salt = vec![0u8; SALT_LEN];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| StegError::InvalidOptions(String::from("Unable to generate random salt")))?;
+    Ok(salt)
+}
+
+// New random seed for a permuted traversal.
+pub fn new_traversal_seed() -> Result<u64, StegError> {
+    let mut seed = [0u8; SEED_LEN];
+    SystemRandom::new()
+        .fill(&mut seed)
+        .map_err(|_| StegError::InvalidOptions(String::from("Unable to generate random traversal seed")))?;
+    Ok(u64::from_be_bytes(seed))
+}
+
+// Each key is only ever used once, as the salt is new for each
+// embedding, so a fixed nonce is safe.
+fn container_key(key: &[u8; KEY_LEN]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&aead::CHACHA20_POLY1305, key).expect("ChaCha20 key length"))
+}
+
+// Encrypt data, appending the authentication tag.
+pub fn seal(key: &[u8; KEY_LEN], mut data: Vec<u8>) -> Vec<u8> {
+    container_key(key)
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key([0u8; 12]), Aad::empty(), &mut data)
+        .expect("ChaCha20 data length");
+    data
+}
+
+// Decrypt data, checking the authentication tag.
+pub fn open(key: &[u8; KEY_LEN], mut data: Vec<u8>) -> Result<Vec<u8>, StegError> {
+    let plain_len = container_key(key)
+        .open_in_place(Nonce::assume_unique_for_key([0u8; 12]), Aad::empty(), &mut data)
+        .map_err(|_| StegError::CorruptHeader(String::from("Embedded data failed authentication")))?
+        .len();
+    data.truncate(plain_len);
+    Ok(data)
+}
+
+pub fn compress(data: &[u8]) -> Result<Vec<u8>, StegError> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+// Decompress the number of files and the file sections.
+// Only as many bytes are decompressed as the file sections declare,
+// and no more than the limit in total, so that crafted data can't
+// make extracting run out of memory.
+pub fn decompress(data: &[u8], limit: u64) -> Result<Vec<u8>, StegError> {
+    let mut decoder = DeflateDecoder::new(data);
+    let mut decompressed = Vec::new();
+    let num_files = decompress_number(&mut decoder, &mut decompressed, 3, limit, "number of files")?;
+    for _file in 0..num_files {
+        let file_name_len = decompress_number(&mut decoder, &mut decompressed, 3, limit, "file name length")?;
+        decompress_exact(&mut decoder, &mut decompressed, file_name_len, limit, "file name")?;
+        let file_len = decompress_number(&mut decoder, &mut decompressed, 10, limit, "file length")?;
+        decompress_exact(&mut decoder, &mut decompressed, file_len, limit, "file data")?;
+    }
+
+    // Nothing should follow the last file.
+    let mut extra = [0u8; 1];
+    if decoder.read(&mut extra).map_err(|_| decompress_error())? != 0 {
+        error!("Embedded data decompresses to more than its files.");
+        return Err(StegError::CorruptHeader(String::from("Embedded data larger than declared")));
+    }
+    Ok(decompressed)
+}
+
+fn decompress_error() -> StegError {
+    StegError::CorruptHeader(String::from("Embedded data failed to decompress"))
+}
+
+// Decompress exactly len more bytes, as long as the total
+// decompressed stays within the limit.
+fn decompress_exact(decoder:&mut impl Read, decompressed:&mut Vec<u8>, len:u64, limit:u64, field:&str) -> Result<(), StegError> {
+    if (decompressed.len() as u64).saturating_add(len) > limit {
+        error!("Embedded data decompresses to more than: {} bytes", limit);
+        return Err(StegError::CorruptHeader(format!("Embedded data decompresses to more than {} bytes", limit)));
+    }
+    let bytes_read = decoder.take(len).read_to_end(decompressed).map_err(|_| decompress_error())?;
+    if bytes_read as u64 != len {
+        return Err(StegError::CorruptHeader(format!("Truncated {}", field)));
+    }
+    Ok(())
+}
+
+// Decompress a number header field, of a fixed number of digits.
+fn decompress_number(decoder:&mut impl Read, decompressed:&mut Vec<u8>, digits:u64, limit:u64, field:&str) -> Result<u64, StegError> {
+    let start = decompressed.len();
+    decompress_exact(decoder, decompressed, digits, limit, field)?;
+    std::str::from_utf8(&decompressed[start..])
+        .ok()
+        .and_then(|digits| digits.parse().ok())
+        .ok_or_else(|| StegError::CorruptHeader(format!("Invalid {}", field)))
+}
+
+// Number of bytes that data takes up in the image,
+// once repeated for error correction.
+pub fn fec_len(len: u64, copies: u8) -> u64 {
+    len.saturating_mul(copies as u64)
+}
+
+// Method to write data to the image, repeating each block for error correction.
+impl Steganography {
+    pub fn write_fec(&mut self, bytes:&[u8], copies:u8) -> Result<(), StegError> {
+        for block in bytes.chunks(FEC_BLOCK) {
+            self.progress.check_cancelled()?;
+            for _copy in 0..copies {
+                self.write_all_to_image(block)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Method to read data from the image, taking the majority
+// vote of each bit over the copies of each block.
+impl Steganography {
+    pub fn read_fec(&mut self, len:u64, copies:u8) -> Result<Vec<u8>, StegError> {
+        let mut data = Vec::with_capacity(len as usize);
+        let mut remaining = len;
+        while remaining > 0 {
+            let block_len = remaining.min(FEC_BLOCK as u64) as usize;
+
+            // Read all the copies of the block.
+            let mut block_copies = Vec::with_capacity(copies as usize);
+            for _copy in 0..copies {
+                let mut block = vec![0u8; block_len];
+                if self.read_data_into(&mut block) != block_len {
+                    error!("Embedded data runs past the end of the image.");
+                    return Err(StegError::CorruptHeader(String::from("Embedded data truncated")));
+                }
+                block_copies.push(block);
+            }
+
+            // Each bit is set if it is set in most of the copies.
+            for idx in 0..block_len {
+                let mut byte = 0u8;
+                for bit in 0..8 {
+                    let votes = block_copies.iter().filter(|block| block[idx] & (1 << bit) != 0).count();
+                    if votes * 2 > copies as usize {
+                        byte |= 1 << bit;
+                    }
+                }
+                data.push(byte);
+            }
+            remaining -= block_len as u64;
+        }
+        Ok(data)
+    }
+}
+
+// Method to read the container header, following the container marker.
+impl Steganography {
+    pub fn read_container_header(&mut self) -> Result<ContainerHeader, StegError> {
+        let version = self.read_header_number(1, "container version")? as u8;
+        if version != CONTAINER_VERSION {
+            return Err(StegError::CorruptHeader(format!("Unsupported container version: {}", version)));
+        }
+
+        self.read_data_from_image(1);
+        if self.bytes_read != 1 {
+            return Err(StegError::CorruptHeader(String::from("Truncated container flags")));
+        }
+        let flags = self.code_bytes[0];
+        if flags & !FLAGS_KNOWN != 0 {
+            return Err(StegError::CorruptHeader(format!("Unknown container flags: {:#04x}", flags)));
+        }
+
+        let copies = self.read_header_number(1, "error correction")? as u8;
+        if crate::steg::options::FecLevel::from_copies(copies).is_none() {
+            return Err(StegError::CorruptHeader(format!("Invalid error correction copies: {}", copies)));
+        }
+
+        let has_password = flags & FLAG_PASSWORD != 0;
+        let encrypted = flags & FLAG_ENCRYPTED != 0;
+        if encrypted && !has_password {
+            return Err(StegError::CorruptHeader(String::from("Encrypted without a password")));
+        }
+
+        // Key derivation parameters, if there is a password.
+        let kdf = if has_password {
+            self.read_data_from_image(SALT_LEN as u32);
+            if self.bytes_read != SALT_LEN as u32 {
+                return Err(StegError::CorruptHeader(String::from("Truncated password salt")));
+            }
+            let salt = self.code_bytes.clone();
+            let iterations = self.read_header_number(10, "key derivation iterations")?;
+            let iterations = u32::try_from(iterations)
+                .map_err(|_| StegError::CorruptHeader(format!("Invalid key derivation iterations: {}", iterations)))?;
+            self.read_data_from_image(VERIFIER_LEN as u32);
+            if self.bytes_read != VERIFIER_LEN as u32 {
+                return Err(StegError::CorruptHeader(String::from("Truncated password verifier")));
+            }
+            let verifier = self.code_bytes.clone();
+            Some(KdfParams { salt, iterations, verifier })
+        }
+        else {
+            None
+        };
+
+        // Traversal seed, if the data is permuted.
+        let traversal_seed = if flags & FLAG_PERMUTED != 0 {
+            self.read_data_from_image(SEED_LEN as u32);
+            if self.bytes_read != SEED_LEN as u32 {
+                return Err(StegError::CorruptHeader(String::from("Truncated traversal seed")));
+            }
+            let mut seed = [0u8; SEED_LEN];
+            seed.copy_from_slice(&self.code_bytes);
+            Some(u64::from_be_bytes(seed))
+        }
+        else {
+            None
+        };
+
+        let header = ContainerHeader {
+            version,
+            has_password,
+            encrypted,
+            compressed: flags & FLAG_COMPRESSED != 0,
+            fec_copies: copies,
+            kdf,
+            traversal_seed,
+        };
+        info!("Container version: {}, flags: {:#04x}, copies: {}", version, flags, copies);
+        Ok(header)
+    }
+}
+
+// Method to check a password against the container password verifier.
+// Returns the key derived from the password.
+impl Steganography {
+    pub fn check_container_password(&self, password:&str) -> Result<Key, StegError> {
+        let kdf = match &self.container.kdf {
+            Some(kdf) => kdf,
+            None => return Err(StegError::CorruptHeader(String::from("No password parameters"))),
+        };
+        let (key, verifier) = derive_key(password, &kdf.salt, kdf.iterations)?;
+        match constant_time::verify_slices_are_equal(&verifier, &kdf.verifier) {
+            Ok(()) => Ok(key),
+            Err(_) => Err(StegError::BadPassword),
+        }
+    }
+}
+
+// Method to read the container data from the image, and undo
+// the error correction, encryption and compression.
+// Returns the number of files and file sections.
+impl Steganography {
+    pub fn read_container_data(&mut self, key:Option<&[u8; KEY_LEN]>) -> Result<Vec<u8>, StegError> {
+        let data_len = self.read_container_data_len()?;
+        self.start_traversal(self.container.traversal_seed, data_len, self.container.fec_copies);
+        let data = self.read_fec(data_len, self.container.fec_copies);
+        self.permutation = None;
+        let mut data = data?;
+
+        if self.container.encrypted {
+            match key {
+                Some(key) => data = open(key, data)?,
+                None => return Err(StegError::BadPassword),
+            }
+        }
+        if self.container.compressed {
+            data = decompress(&data, self.settings.max_decompressed_len)?;
+        }
+        Ok(data)
+    }
+}
+
+// Method to start reading or writing the container data, from the
+// cursor, in the order of the traversal.
+// Without a seed the data is read or written sequentially.
+impl Steganography {
+    pub fn start_traversal(&mut self, seed:Option<u64>, data_len:u64, copies:u8) {
+        self.permutation = seed.map(|seed| {
+            let data_bits = fec_len(data_len, copies).saturating_mul(8);
+            Permutation::new(seed, self.bit_index(), data_bits, self.pic_pixels() * 3, self.bit_capacity())
+        });
+    }
+}
+
+// Method to read the length of the container data, checking
+// that the data is all within the image.
+impl Steganography {
+    fn read_container_data_len(&mut self) -> Result<u64, StegError> {
+        let data_len = self.read_header_number(10, "data length")?;
+        let remaining = (self.bit_capacity() - self.bit_index()) / 8;
+        if fec_len(data_len, self.container.fec_copies) > remaining {
+            return Err(StegError::CorruptHeader(String::from("Embedded data truncated")));
+        }
+        Ok(data_len)
+    }
+}
+
+// Method to read the number of files from the container data,
+// without reading the rest of the data.
+// Only the first error correction block is read, and only the
+// number of files is decompressed from it.
+impl Steganography {
+    pub fn read_container_file_count(&mut self) -> Result<u64, StegError> {
+        if self.container.encrypted {
+            return Err(StegError::BadPassword);
+        }
+        let data_len = self.read_container_data_len()?;
+        self.start_traversal(self.container.traversal_seed, data_len, self.container.fec_copies);
+        let block = self.read_fec(data_len.min(FEC_BLOCK as u64), self.container.fec_copies);
+        self.permutation = None;
+        let block = block?;
+        let mut digits = Vec::new();
+        if self.container.compressed {
+            decompress_number(&mut DeflateDecoder::new(&block[..]), &mut digits, 3, 3, "number of files")
+        }
+        else {
+            decompress_number(&mut &block[..], &mut digits, 3, 3, "number of files")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::steg::embed::PayloadEntry;
+    use crate::steg::options::{Compression, EmbedOptions, FecLevel, Traversal};
+    use crate::steg::testing::loaded_steg;
+
+    // Number of files, and a single file section.
+    fn file_sections(contents:&[u8]) -> Vec<u8> {
+        let mut data = format!("001005a.txt{:0>10}", contents.len()).into_bytes();
+        data.extend_from_slice(contents);
+        data
+    }
+
+    #[test]
+    fn decompress_restores_file_sections() {
+        let data = file_sections(&[7u8; 5000]);
+        assert_eq!(decompress(&compress(&data).unwrap(), u64::MAX).unwrap(), data);
+    }
+
+    #[test]
+    fn decompress_stops_at_limit() {
+        // Declared lengths are all within the data, but over the limit.
+        let data = file_sections(&[7u8; 5000]);
+        let compressed = compress(&data).unwrap();
+        assert_eq!(decompress(&compressed, data.len() as u64).unwrap(), data);
+        assert!(matches!(decompress(&compressed, data.len() as u64 - 1), Err(StegError::CorruptHeader(_))));
+    }
+
+    #[test]
+    fn decompress_stops_at_declared_length() {
+        // A few bytes of file, followed by a lot of data that compresses well.
+        let mut data = file_sections(b"abc");
+        data.resize(data.len() + 16 * 1024 * 1024, 0);
+        let compressed = compress(&data).unwrap();
+        assert!(matches!(decompress(&compressed, u64::MAX), Err(StegError::CorruptHeader(_))));
+    }
+
+    #[test]
+    fn decompress_rejects_truncated_file() {
+        let mut data = file_sections(b"abcdef");
+        data.truncate(data.len() - 2);
+        assert!(matches!(decompress(&compress(&data).unwrap(), u64::MAX), Err(StegError::CorruptHeader(_))));
+    }
+
+    // Number of files is read from the start of the container data,
+    // which is longer than one error correction block.
+    #[test]
+    fn file_count_read_from_first_block() {
+        let contents:Vec<Vec<u8>> = (0..3u32).map(|seed| (0..3000u32).map(|idx| (idx * 7919 + seed * 31).to_le_bytes()[1]).collect()).collect();
+        for compression in [Compression::None, Compression::Deflate] {
+            let mut steg = loaded_steg(400, 400);
+            let entries = contents.iter().enumerate().map(|(idx, bytes)| PayloadEntry::from_bytes(&format!("{}.bin", idx), bytes)).collect();
+            let options = EmbedOptions::builder().compression(compression).fec_level(FecLevel::Repeat3).build().unwrap();
+            steg.embed(entries, &options).unwrap();
+            assert_eq!(steg.count_embedded_files().unwrap(), Some(3));
+        }
+    }
+
+    // Permuted data reads back the same, is recorded in the header,
+    // and is scattered over the whole image, not just the top of it.
+    #[test]
+    fn permuted_traversal_round_trips() {
+        let contents:Vec<u8> = (0..2000u32).map(|idx| (idx * 31 + idx / 7) as u8).collect();
+        for fec_level in [FecLevel::None, FecLevel::Repeat3] {
+            let mut steg = loaded_steg(200, 200);
+            let options = EmbedOptions::builder().traversal(Traversal::Permuted).fec_level(fec_level).build().unwrap();
+            steg.embed(vec![PayloadEntry::from_bytes("a.bin", &contents)], &options).unwrap();
+            assert!(steg.container.traversal_seed.is_some());
+
+            let files = steg.extract_to_memory("").unwrap();
+            assert_eq!(files.len(), 1);
+            assert_eq!(files[0].bytes, contents);
+
+            let image = steg.image.as_ref().unwrap().to_rgb8();
+            let bottom_changed = image.rows().skip(150).flatten().any(|pixel| pixel.0 != [0, 0, 0]);
+            assert!(bottom_changed, "fec level: {:?}", fec_level);
+        }
+    }
+}
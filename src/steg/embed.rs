@@ -0,0 +1,498 @@
+// Embedding methods.
+//
+// Files are embedded from payload entries, each a name and a source
+// of a declared number of bytes, so that data doesn't have to be
+// in a file on disk to be embedded.
+// Embedding files from paths is a convenience built on top of this.
+// How the files are embedded is set by the embedding options.
+
+use crate::steg::container;
+use crate::steg::options::{Compression, EmbedOptions, EmbedOptionsBuilder, Encryption, Traversal};
+use crate::steg::progress::ProgressReader;
+use crate::steg::{StegError, Steganography};
+
+use log::{error, info, warn};
+use ring::digest;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+
+// Largest values that fit in the embedded header fields.
+pub const MAX_FILES: usize = 999;
+pub const MAX_NAME_LEN: usize = 999;
+pub const MAX_FILE_LEN: u64 = 9_999_999_999;
+
+// Something to embed in an image.
+// The source must provide exactly len bytes.
+pub struct PayloadEntry<'a> {
+    pub name: String,
+    pub source: Box<dyn Read + Send + 'a>,
+    pub len: u64,
+}
+
+impl<'a> PayloadEntry<'a> {
+    // Entry read from any source, of a declared length.
+    pub fn new(name: &str, source: impl Read + Send + 'a, len: u64) -> Self {
+        PayloadEntry { name: name.to_string(), source: Box::new(source), len }
+    }
+
+    // Entry from a buffer in memory.
+    pub fn from_bytes(name: &str, bytes: &'a [u8]) -> Self {
+        PayloadEntry::new(name, Cursor::new(bytes), bytes.len() as u64)
+    }
+
+    // Entry from a source of unknown length, such as a pipe.
+    // The source is read into memory, as the length of an entry
+    // is embedded before its contents.
+    pub fn from_reader(name: &str, source: impl Read) -> Result<PayloadEntry<'static>, StegError> {
+        let mut bytes = Vec::new();
+        source.take(MAX_FILE_LEN + 1).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 > MAX_FILE_LEN {
+            return Err(StegError::InvalidPayload(format!("File too large to embed: {}", name)));
+        }
+        let len = bytes.len() as u64;
+        Ok(PayloadEntry::new(name, Cursor::new(bytes), len))
+    }
+
+    // Entry from a file on disk, named after the file.
+    pub fn from_path(file_path: &str) -> Result<Self, StegError> {
+        let file_name = match Path::new(file_path).file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => return Err(StegError::InvalidPayload(format!("No file name in path: {}", file_path))),
+        };
+        let file = File::open(file_path)?;
+        let len = file.metadata()?.len();
+        Ok(PayloadEntry::new(&file_name, file, len))
+    }
+}
+
+// Method to embed one or more files into a loaded image.
+impl Steganography {
+    pub fn embed_files(&mut self, pw:bool, pw_str:&str, files_to_embed:&[&str]) -> Result<(), StegError> {
+        // Open each of the files to embed.
+        let mut entries = Vec::with_capacity(files_to_embed.len());
+        for file in files_to_embed {
+            entries.push(PayloadEntry::from_path(file)?);
+        }
+        self.embed_entries(pw, pw_str, entries)
+    }
+}
+
+// Method to embed one or more payload entries into a loaded image,
+// with the default options from the settings, and an optional password.
+impl Steganography {
+    pub fn embed_entries(&mut self, pw:bool, pw_str:&str, entries:Vec<PayloadEntry>) -> Result<(), StegError> {
+        let mut builder = EmbedOptionsBuilder::from_settings(&self.settings);
+        if pw {
+            builder = builder.password(pw_str);
+        }
+        let options = builder.build()?;
+        self.embed(entries, &options)
+    }
+}
+
+// Method to embed one or more payload entries into a loaded image.
+// If embedding fails, or is cancelled, part way through then
+// the image is rolled back to how it was before embedding.
+impl Steganography {
+    pub fn embed(&mut self, entries:Vec<PayloadEntry>, options:&EmbedOptions) -> Result<(), StegError> {
+        let original_image = self.image.clone();
+        let result = self.write_embedding(entries, options);
+        if result.is_err() {
+            warn!("Embedding failed, restoring original image.");
+            self.image = original_image;
+            return result;
+        }
+        // Image is now pic coded, with the new embedding,
+        // which hasn't been saved yet, and is to be saved
+        // in the output format, if one was given.
+        self.img_changed = true;
+        self.save_format = options.output_format;
+        self.recheck_embedding()
+    }
+}
+
+// Method to write payload entries into a loaded image.
+// Everything is checked, including that the entries fit in the image,
+// before the image is changed.
+impl Steganography {
+    fn write_embedding(&mut self, entries:Vec<PayloadEntry>, options:&EmbedOptions) -> Result<(), StegError> {
+        // Don't need to initialise image parameters as we require
+        // a loaded image to embed files into.
+        if !self.img_to_proc {
+            info!("No image to embed into.");
+            return Err(StegError::NoImage);
+        }
+
+        // We have an image to embed into so all good.
+        // It doesn't matter if the image is already pic coded as we
+        // will just overwrite the previous embedding.
+
+        // Check that the entries fit in the embedded header fields.
+        if entries.len() > MAX_FILES {
+            return Err(StegError::InvalidPayload(format!("Too many files to embed: {}", entries.len())));
+        }
+        for entry in &entries {
+            if entry.name.is_empty() || entry.name.len() > MAX_NAME_LEN {
+                return Err(StegError::InvalidPayload(format!("Invalid file name length: {:?}", entry.name)));
+            }
+            if entry.len > MAX_FILE_LEN {
+                return Err(StegError::InvalidPayload(format!("File too large to embed: {}", entry.name)));
+            }
+            info!("File: {} Size: {} bytes", entry.name, entry.len);
+        }
+
+        // Check the exact number of bytes needed, header and file
+        // sections included, against the bytes available.
+        // Compressed data can only be checked once it has been compressed.
+        let available = self.usable_capacity(options);
+        let required = self.required_bytes(&entries, options);
+        if required > available && options.compression == Compression::None {
+            // Exceeded embedding capacity so can't imbed.
+            warn!("Exceeded image emdedding by: {} bytes", required - available);
+            return Err(StegError::CapacityExceeded { required, available });
+        }
+
+        if options.needs_container() {
+            return self.embed_container(entries, options, available);
+        }
+
+        // Within the embedding capacity of the image, so proceed.
+        info!("Total data to embed: {} bytes", required);
+
+        // First step is to write the preamble to the file.
+        self.embed_preamble()?;
+
+        // Next we need to embed a password if required.
+        match &options.password {
+            Some(password) => self.embed_password(true, password)?,
+            None => self.embed_password(false, "")?,
+        }
+
+        // Next need to embed the number of files we are embedding.
+        let num_files:u16 = entries.len() as u16;
+        self.embed_num_of_files(num_files)?;
+
+        // Next need to embed files themselves, one at a time.
+        for mut entry in entries {
+            // Need to embed the file.
+            // This also means embeddng the name of the file,
+            // and the length of the file.
+            self.embed_file(&mut entry)?;
+            info!("Successfully embedded file: {}", entry.name);
+        }
+        Ok(())
+    }
+}
+
+// Method to embed payload entries in a versioned container.
+// The data is put together in memory, as it is compressed and
+// encrypted as a whole, before anything is written to the image.
+impl Steganography {
+    fn embed_container(&mut self, entries:Vec<PayloadEntry>, options:&EmbedOptions, available:u64) -> Result<(), StegError> {
+        // Number of files and file sections, as in the original format.
+        let mut data:Vec<u8> = format!("{:0>3}", entries.len()).into_bytes();
+        for mut entry in entries {
+            data.extend_from_slice(&entry_header(&entry));
+            let source = (&mut entry.source).take(entry.len);
+            let bytes_read = ProgressReader::new(source, &entry.name, entry.len, &mut self.progress)
+                .read_to_end(&mut data)? as u64;
+            if bytes_read != entry.len {
+                return Err(StegError::Io(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("Source ended {} bytes early: {}", entry.len - bytes_read, entry.name),
+                )));
+            }
+        }
+
+        let mut flags = 0u8;
+        if options.compression == Compression::Deflate {
+            data = container::compress(&data)?;
+            flags |= container::FLAG_COMPRESSED;
+        }
+
+        // Container header, up to the data length.
+        let copies = options.fec_level.copies();
+        let mut header = vec![container::CONTAINER_MARKER, b'0' + container::CONTAINER_VERSION, 0, b'0' + copies];
+        if let Some(password) = &options.password {
+            flags |= container::FLAG_PASSWORD;
+            let salt = container::new_salt()?;
+            let (key, verifier) = container::derive_key(password, &salt, options.kdf_iterations)?;
+            header.extend_from_slice(&salt);
+            header.extend_from_slice(format!("{:0>10}", options.kdf_iterations).as_bytes());
+            header.extend_from_slice(&verifier);
+            if options.encryption == Encryption::ChaCha20Poly1305 {
+                data = container::seal(&key, data);
+                flags |= container::FLAG_ENCRYPTED;
+            }
+        }
+        let traversal_seed = match options.traversal {
+            Traversal::Sequential => None,
+            Traversal::Permuted => Some(container::new_traversal_seed()?),
+        };
+        if let Some(seed) = traversal_seed {
+            flags |= container::FLAG_PERMUTED;
+            header.extend_from_slice(&seed.to_be_bytes());
+        }
+        header[2] = flags;
+        header.extend_from_slice(format!("{:0>10}", data.len()).as_bytes());
+
+        // Check everything fits before touching the image.
+        let required = self.header_bytes(options).saturating_add(container::fec_len(data.len() as u64, copies));
+        if required > available {
+            warn!("Exceeded image emdedding by: {} bytes", required - available);
+            return Err(StegError::CapacityExceeded { required, available });
+        }
+        info!("Total data to embed: {} bytes", required);
+
+        self.embed_preamble()?;
+        self.write_all_to_image(&header)?;
+        self.start_traversal(traversal_seed, data.len() as u64, copies);
+        let result = self.write_fec(&data, copies);
+        self.permutation = None;
+        result?;
+        info!("Container data written to image successfully.");
+        Ok(())
+    }
+}
+
+// Header for an embedded file: the file name length,
+// the file name, and the file length.
+pub fn entry_header(entry:&PayloadEntry) -> Vec<u8> {
+    // Format file name length to 3 digits, with leading 0s.
+    // File length in bytes formatted to 10 digits, with leading 0s.
+    let name_bytes = entry.name.as_bytes();
+    let mut header = format!("{:0>3}", name_bytes.len()).into_bytes();
+    header.extend_from_slice(name_bytes);
+    header.extend_from_slice(format!("{:0>10}", entry.len).as_bytes());
+    header
+}
+
+// Method to write bytes to the image, a chunk at a time,
+// checking that all the bytes were written.
+impl Steganography {
+    pub fn write_all_to_image(&mut self, bytes:&[u8]) -> Result<(), StegError> {
+        let start = self.bit_index() / 8;
+        for chunk in bytes.chunks(self.settings.byte_chunk.max(1) as usize) {
+            let bytes_written:u32 = self.write_data_to_image(chunk);
+            if bytes_written != chunk.len() as u32 {
+                error!("Incorrect number of bytes written: {}", bytes_written);
+                return Err(StegError::CapacityExceeded {
+                    required: start + bytes.len() as u64,
+                    available: self.bit_capacity() / 8,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// Method to add the preable code to the image.
+impl Steganography {
+    pub fn embed_preamble(&mut self) -> Result<(), StegError> {
+        info!("Embedding preamble into image.");
+
+        // Initialise embedding parameters.
+        // Reset before preabmle, NEVER after the preamble
+        // else will overwrite early data.
+        self.init_embed_params();
+
+        // Send preamble as bytes vector for embedding.
+        // All writes to the image is done in chunks.
+        let preamble_string = self.settings.prog_code.clone();
+        self.write_all_to_image(preamble_string.as_bytes())
+    }
+}
+
+// Method to embed password (if required) to the image.
+impl Steganography {
+    pub fn embed_password(&mut self, _pw:bool, _pw_str:&str) -> Result<(), StegError> {
+        info!("Embedding whether passworded or not.");
+
+        // Send pasword as applicable as bytes vector for embedding.
+        // All writes to the image is done in chunks.
+        if !_pw {
+            self.write_all_to_image(b"N")
+        }
+        else {
+            // We have a password to embed.
+            // First we need to get the hash of the password to embed.
+            info!("Embedding passworded.");
+            // First the tag that there is a password.
+            let have_pw_bytes = b"Y";
+            // Next we have the hashed password.
+            let digest = digest::digest(&digest::SHA256, _pw_str.as_bytes());
+            let password_bytes = digest.as_ref();
+            // Concatenate the two.
+            let pw_bytes:Vec<u8> = [have_pw_bytes, password_bytes].concat();
+            // Embed into image.
+            self.write_all_to_image(&pw_bytes)
+        }
+    }
+}
+
+// Method to embed the number of files being embedded.
+impl Steganography {
+    pub fn embed_num_of_files(&mut self, num_files:u16) -> Result<(), StegError> {
+        info!("Embedding number of files: {}", num_files);
+
+        // Get the number of files as a string with leading 0s.
+        let _num_files:String = format!("{:0>3}", num_files);
+
+        // Embed into image.
+        self.write_all_to_image(_num_files.as_bytes())
+    }
+}
+
+// Method to embed the contents of a payload entry into the image.
+impl Steganography {
+    pub fn embed_file(&mut self, entry:&mut PayloadEntry) -> Result<(), StegError> {
+        info!("Embedding file: {}", entry.name);
+
+        // Need to embed the file name, and the length of this
+        // file name, as both are embedded, then the file length.
+        let file_detail_bytes = entry_header(entry);
+        // Embed into image.
+        self.write_all_to_image(&file_detail_bytes)?;
+
+        // Now the file needs to be written to the image.
+        // Will do this by reading chunks of data from the source at a time,
+        // and writing the data to the image, until the file is done.
+        // Only the declared length is read from the source.
+        let mut source = (&mut entry.source).take(entry.len);
+
+        // Define a buffer to use for the chunks of read data.
+        let mut buffer = vec![0u8; self.settings.byte_chunk.max(1) as usize];
+
+        // Loop until there are no bytes in the source to write.
+        let mut bytes_remaining = entry.len;
+        while bytes_remaining > 0 {
+            // Stop if the job has been cancelled.
+            self.progress.check_cancelled()?;
+
+            // Read a chunk of data from the source.
+            let bytes_read = source.read(&mut buffer)?;
+
+            // If no bytes were read, the source is shorter than declared.
+            if bytes_read == 0 {
+                return Err(StegError::Io(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("Source ended {} bytes early: {}", bytes_remaining, entry.name),
+                )));
+            }
+
+            // Write the chunk of data to the image.
+            // This checks that the correct number of bytes were written.
+            self.write_all_to_image(&buffer[..bytes_read])?;
+            bytes_remaining -= bytes_read as u64;
+            self.progress.report(&entry.name, entry.len - bytes_remaining, entry.len);
+        }
+        // Return ok result.
+        info!("File data written to image successfully.");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::steg::testing::loaded_steg;
+
+    // Entries from each kind of source are extracted as they were embedded.
+    #[test]
+    fn entries_round_trip() {
+        let bytes:Vec<u8> = (0..5000u32).map(|idx| (idx * 7919).to_le_bytes()[1]).collect();
+        for compression in [Compression::None, Compression::Deflate] {
+            let mut steg = loaded_steg(100, 100);
+            let entries = vec![
+                PayloadEntry::from_bytes("bytes.bin", &bytes),
+                PayloadEntry::from_reader("reader.bin", &bytes[..1000]).unwrap(),
+                PayloadEntry::new("source.bin", &bytes[..], 10),
+            ];
+            let options = EmbedOptions::builder().compression(compression).build().unwrap();
+            steg.embed(entries, &options).unwrap();
+
+            let files = steg.extract_to_memory("").unwrap();
+            let names:Vec<&str> = files.iter().map(|file| file.name.as_str()).collect();
+            assert_eq!(names, ["bytes.bin", "reader.bin", "source.bin"]);
+            assert_eq!(files[0].bytes, bytes);
+            assert_eq!(files[1].bytes, &bytes[..1000]);
+            assert_eq!(files[2].bytes, &bytes[..10]);
+        }
+    }
+
+    // Entries that don't fit in the embedded header fields
+    // are rejected before the image is changed.
+    #[test]
+    fn entry_limits_are_checked() {
+        let long_name = "x".repeat(MAX_NAME_LEN + 1);
+        let invalid:Vec<Vec<PayloadEntry>> = vec![
+            vec![PayloadEntry::from_bytes("", b"a")],
+            vec![PayloadEntry::from_bytes(&long_name, b"a")],
+            vec![PayloadEntry::new("huge.bin", io::empty(), MAX_FILE_LEN + 1)],
+            (0..=MAX_FILES).map(|idx| PayloadEntry::from_bytes(&idx.to_string(), b"")).collect(),
+        ];
+        let options = EmbedOptions::builder().build().unwrap();
+        for entries in invalid {
+            let mut steg = loaded_steg(100, 100);
+            let original_image = steg.image.clone();
+            assert!(matches!(steg.embed(entries, &options), Err(StegError::InvalidPayload(_))));
+            assert_eq!(steg.image, original_image);
+        }
+
+        // Longest name is allowed.
+        let mut steg = loaded_steg(100, 100);
+        let name = "x".repeat(MAX_NAME_LEN);
+        steg.embed(vec![PayloadEntry::from_bytes(&name, b"a")], &options).unwrap();
+        assert_eq!(steg.extract_to_memory("").unwrap()[0].name, name);
+    }
+
+    // A source shorter than its declared length fails,
+    // and the image is rolled back.
+    #[test]
+    fn short_source_is_rejected() {
+        for compression in [Compression::None, Compression::Deflate] {
+            let mut steg = loaded_steg(100, 100);
+            let original_image = steg.image.clone();
+            let options = EmbedOptions::builder().compression(compression).build().unwrap();
+            let result = steg.embed(vec![PayloadEntry::new("short.bin", &b"abc"[..], 5)], &options);
+            assert!(matches!(result, Err(StegError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof));
+            assert_eq!(steg.image, original_image);
+        }
+    }
+
+    // Reader of unknown length that gives its data in small pieces, like a pipe.
+    struct PipeReader {
+        data: Vec<u8>,
+        position: usize,
+    }
+
+    impl Read for PipeReader {
+        fn read(&mut self, buf:&mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.data.len() - self.position).min(37);
+            buf[..len].copy_from_slice(&self.data[self.position..self.position + len]);
+            self.position += len;
+            Ok(len)
+        }
+    }
+
+    // Piped data is embedded as a single entry,
+    // and can be streamed back out as it was.
+    #[test]
+    fn piped_data_is_one_entry() {
+        let data:Vec<u8> = (0..30_000u32).map(|idx| (idx * 7919).to_le_bytes()[1]).collect();
+        for compression in [Compression::None, Compression::Deflate] {
+            let mut steg = loaded_steg(200, 200);
+            let entry = PayloadEntry::from_reader("stdin", PipeReader { data: data.clone(), position: 0 }).unwrap();
+            assert_eq!(entry.len, data.len() as u64);
+            let options = EmbedOptions::builder().compression(compression).build().unwrap();
+            steg.embed(vec![entry], &options).unwrap();
+
+            assert_eq!(steg.count_embedded_files().unwrap(), Some(1));
+            let mut out = Vec::new();
+            let metadata = steg.extract_entry_to("", None, &mut out).unwrap();
+            assert_eq!((metadata.index, metadata.size), (0, data.len() as u64));
+            assert_eq!(out, data);
+        }
+    }
+}
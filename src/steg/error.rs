@@ -0,0 +1,95 @@
+// Steganography error type.
+//
+// All the ways that loading, embedding, extracting and saving
+// an image can fail, so that callers can react to them.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum StegError {
+    // No image has been loaded to work with.
+    NoImage,
+    // Image doesn't contain the pic code, so has nothing embedded.
+    NotCoded,
+    // Password given doesn't match the one embedded in the image.
+    BadPassword,
+    // Data to embed can't be described in the embedded header.
+    InvalidPayload(String),
+    // Embedding options that can't be used together, or at all.
+    InvalidOptions(String),
+    // Data to embed doesn't fit in the image.
+    CapacityExceeded { required: u64, available: u64 },
+    // Image isn't an 8 bit rgb or rgba image.
+    UnsupportedColourType(String),
+    // No embedded file with the name asked for.
+    EntryNotFound(String),
+    // Embedded header data can't be read or makes no sense.
+    CorruptHeader(String),
+    // Images to compare aren't the same width and height.
+    SizeMismatch { expected: (u32, u32), found: (u32, u32) },
+    // Job cancelled through its cancellation token.
+    Cancelled,
+    // Failure reading or writing files.
+    Io(io::Error),
+    // Failure opening or decoding an image file.
+    Open(image::ImageError),
+    // Failure encoding or writing an image file.
+    Save(image::ImageError),
+}
+
+impl fmt::Display for StegError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StegError::NoImage => write!(f, "No image loaded"),
+            StegError::NotCoded => write!(f, "Image is not pic coded"),
+            StegError::BadPassword => write!(f, "Password does not match"),
+            StegError::InvalidPayload(reason) => write!(f, "Invalid payload: {}", reason),
+            StegError::InvalidOptions(reason) => write!(f, "Invalid options: {}", reason),
+            StegError::CapacityExceeded { required, available } => write!(
+                f,
+                "Exceeded image embedding capacity, required: {} bytes, available: {} bytes, short by: {} bytes",
+                required,
+                available,
+                required.saturating_sub(*available)
+            ),
+            StegError::UnsupportedColourType(colour) => {
+                write!(f, "Image not a supported rgb colour type: {}", colour)
+            }
+            StegError::EntryNotFound(name) if name.is_empty() => write!(f, "No embedded files"),
+            StegError::EntryNotFound(name) => write!(f, "No embedded file named: {}", name),
+            StegError::CorruptHeader(reason) => write!(f, "Corrupt embedded header: {}", reason),
+            StegError::SizeMismatch { expected, found } => write!(
+                f,
+                "Images are different sizes: {}x{} and {}x{}",
+                expected.0, expected.1, found.0, found.1
+            ),
+            StegError::Cancelled => write!(f, "Cancelled"),
+            StegError::Io(err) => write!(f, "I/O error: {}", err),
+            StegError::Open(err) => write!(f, "Error opening image: {}", err),
+            StegError::Save(err) => write!(f, "Error saving image: {}", err),
+        }
+    }
+}
+
+impl Error for StegError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StegError::Io(err) => Some(err),
+            StegError::Open(err) | StegError::Save(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// Cancellation passed through io traits, while reading,
+// is turned back into a cancellation.
+impl From<io::Error> for StegError {
+    fn from(err: io::Error) -> Self {
+        match err.get_ref().and_then(|inner| inner.downcast_ref::<StegError>()) {
+            Some(StegError::Cancelled) => StegError::Cancelled,
+            _ => StegError::Io(err),
+        }
+    }
+}
@@ -0,0 +1,440 @@
+// Embedded file extraction methods.
+//
+// Embedded files can be extracted into memory, into the folder for
+// embedded files, or streamed one at a time to a caller supplied sink,
+// so that their contents never have to touch the disk.
+
+use crate::steg::progress::{Progress, ProgressReader};
+use crate::steg::{StegError, Steganography};
+
+use log::{error, info, warn};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufReader, Cursor, Read, Write};
+use std::mem;
+use zeroize::Zeroizing;
+use std::path::{Path, PathBuf};
+
+// Details of a file embedded in an image,
+// other than its name.
+#[derive(Debug, Clone)]
+pub struct EntryMetadata {
+    // Position of the file amongst the embedded files, from 0.
+    pub index: usize,
+    // Length of the file contents in bytes.
+    pub size: u64,
+}
+
+// A file extracted into memory.
+#[derive(Debug, Clone)]
+pub struct ExtractedFile {
+    pub name: String,
+    pub bytes: Vec<u8>,
+    pub metadata: EntryMetadata,
+}
+
+// Destination for the contents of embedded files.
+// Called once for each embedded file, with a reader over its contents.
+// The sink doesn't have to read all (or any) of the contents,
+// the rest of the file is skipped.
+pub trait ExtractSink {
+    fn extract_entry(&mut self, name: &str, metadata: &EntryMetadata, data: &mut dyn Read) -> io::Result<()>;
+}
+
+// Closures can be used as sinks.
+impl<F> ExtractSink for F
+where
+    F: FnMut(&str, &EntryMetadata, &mut dyn Read) -> io::Result<()>,
+{
+    fn extract_entry(&mut self, name: &str, metadata: &EntryMetadata, data: &mut dyn Read) -> io::Result<()> {
+        self(name, metadata, data)
+    }
+}
+
+// Sink that writes embedded files to a folder.
+// Existing files are never overwritten, a numbered suffix is
+// added to the file name instead.
+pub struct FolderSink {
+    pub folder: PathBuf,
+    // Files written so far, with any suffix added to their names.
+    pub written: Vec<PathBuf>,
+}
+
+impl ExtractSink for FolderSink {
+    fn extract_entry(&mut self, name: &str, metadata: &EntryMetadata, data: &mut dyn Read) -> io::Result<()> {
+        info!("Extracting file of size: {}, to: {}.", metadata.size, name);
+
+        // Check if folder for storing embedded files exists.
+        // If it doesn't exist, create it.
+        fs::create_dir_all(&self.folder)?;
+
+        // Get file path for the file to be written.
+        // All files will be written to a specific folder.
+        let mut wrt_path = PathBuf::new();
+        wrt_path.push(&self.folder);
+        wrt_path.push(name);
+        let original_filename = wrt_path.to_string_lossy().into_owned();
+        let mut wrt_path_string = original_filename.clone();
+
+        // Create the file, only if there isn't one with the same name,
+        // so that an existing file is never overwritten, even one
+        // that appears while extracting.
+        // If the name is taken we will add a suffix to the end of the
+        // file name to make it unique, and try again.
+        // No need to manually close as the file will be closed when it goes out of scope.
+        // If the copy fails, or is cancelled, the partly written file is removed,
+        // so that only whole files are left behind.
+        let mut suffix = 1;
+        let mut file = loop {
+            info!("Opening file: {}, for writing.", wrt_path_string);
+            match OpenOptions::new().write(true).create_new(true).open(&wrt_path_string) {
+                Ok(file) => break file,
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(err),
+            }
+            // Construct next suffix.
+            let extension = match original_filename.rfind('.') {
+                Some(idx) => &original_filename[idx..],
+                None => "",
+            };
+            // Construct base file path.
+            let base_filename = if let Some(idx) = original_filename.rfind('.') {
+                &original_filename[..idx]
+            } else {
+                &original_filename
+            };
+            // Construct complete file name.
+            wrt_path_string = format!("{}-{:03}{}", base_filename, suffix, extension);
+            // Increment suffix if this file name exists.
+            suffix += 1;
+        };
+        if let Err(err) = io::copy(data, &mut file) {
+            drop(file);
+            if let Err(remove_err) = fs::remove_file(&wrt_path_string) {
+                warn!("Unable to remove partly written file: {}, {}", wrt_path_string, remove_err);
+            }
+            return Err(err);
+        }
+        info!("Data written to file successfully: {}", name);
+        self.written.push(PathBuf::from(wrt_path_string));
+        Ok(())
+    }
+}
+
+// Method to extract data from file, to the folder for embedded files.
+// Password string required, empty string if no
+// password required.
+impl Steganography {
+    pub fn extract_data(&mut self, pw:String) -> Result<(), StegError> {
+        let pw = Zeroizing::new(pw);
+        let mut sink = FolderSink { folder: PathBuf::from(&self.settings.secret_folder), written: Vec::new() };
+        self.extract_to_sink(&pw, &mut sink)
+    }
+}
+
+// Method to extract all the embedded files into memory.
+impl Steganography {
+    pub fn extract_to_memory(&mut self, pw:&str) -> Result<Vec<ExtractedFile>, StegError> {
+        let mut files = Vec::new();
+        let mut sink = |name: &str, metadata: &EntryMetadata, data: &mut dyn Read| -> io::Result<()> {
+            let mut bytes = Vec::new();
+            data.read_to_end(&mut bytes)?;
+            files.push(ExtractedFile { name: name.to_string(), bytes, metadata: metadata.clone() });
+            Ok(())
+        };
+        self.extract_to_sink(pw, &mut sink)?;
+        Ok(files)
+    }
+}
+
+// Method to write one embedded file to a writer, such as stdout,
+// without writing anything to disk.
+// The file is chosen by name, or else is the first embedded file.
+impl Steganography {
+    pub fn extract_entry_to(&mut self, pw:&str, name:Option<&str>, writer:&mut dyn Write) -> Result<EntryMetadata, StegError> {
+        let mut found:Option<EntryMetadata> = None;
+        let mut sink = |entry_name: &str, metadata: &EntryMetadata, data: &mut dyn Read| -> io::Result<()> {
+            let wanted = match name {
+                Some(name) => name == entry_name,
+                None => metadata.index == 0,
+            };
+            if wanted && found.is_none() {
+                io::copy(data, writer)?;
+                found = Some(metadata.clone());
+            }
+            Ok(())
+        };
+        self.extract_to_sink(pw, &mut sink)?;
+        writer.flush()?;
+        found.ok_or_else(|| StegError::EntryNotFound(name.unwrap_or("").to_string()))
+    }
+}
+
+// Method to stream each embedded file to a sink.
+impl Steganography {
+    pub fn extract_to_sink(&mut self, pw:&str, sink:&mut dyn ExtractSink) -> Result<(), StegError> {
+        // Get to the start of the embedded files.
+        // Files in a versioned container have already been read
+        // from the image, files in the original format are read
+        // from the image as they are extracted.
+        let container_data = self.open_embedded_data(pw)?;
+
+        // Progress is reported while the image is being read,
+        // so is held separately for the extraction.
+        let mut progress = mem::take(&mut self.progress);
+        let result = match container_data {
+            Some(data) => {
+                let available = data.len() as u64;
+                extract_entries(&mut Cursor::new(data), available, sink, &mut progress)
+            }
+            None => {
+                let available = (self.bit_capacity() - self.bit_index()) / 8;
+                let byte_chunk = self.settings.byte_chunk.max(1) as usize;
+                let mut reader = BufReader::with_capacity(byte_chunk, self.reader());
+                extract_entries(&mut reader, available, sink, &mut progress)
+            }
+        };
+        self.progress = progress;
+        result
+    }
+}
+
+// Method to get to the start of the embedded files,
+// checking the password if there is one.
+// Returns the embedded files for a versioned container,
+// or None if the embedded files follow in the image.
+impl Steganography {
+    pub fn open_embedded_data(&mut self, pw:&str) -> Result<Option<Vec<u8>>, StegError> {
+        // Can only extract from a pic coded image.
+        if !self.pic_coded {
+            return Err(StegError::NotCoded);
+        }
+
+        // Embedded data follows the preamble code.
+        self.set_bit_index(self.settings.prog_code.len() as u64 * 8);
+        self.check_for_password()?;
+
+        // Versioned container data is read, and checked, all at once.
+        if self.container.version >= 2 {
+            let key = if self.pic_has_pw {
+                let key = self.check_container_password(pw)?;
+                self.user_permit = true;
+                info!("Correct password provided.");
+                Some(key)
+            }
+            else {
+                None
+            };
+            return self.read_container_data(key.as_deref()).map(Some);
+        }
+
+        // If password required then check it.
+        if self.pic_has_pw {
+            // Password required, so check password provided.
+            self.check_valid_password(pw.to_string())?;
+            info!("Correct password provided.");
+        }
+
+        // Either password not required or correct password entered.
+        // Either way we can proceed with extracting data.
+        Ok(None)
+    }
+}
+
+// Method to count the embedded files, when that can be done
+// without a password.
+// Returns None for images that aren't pic coded, or have a password.
+impl Steganography {
+    pub fn count_embedded_files(&mut self) -> Result<Option<u64>, StegError> {
+        if !self.pic_coded || self.pic_has_pw {
+            return Ok(None);
+        }
+        // Only the header is read, up to the number of files,
+        // so that counting doesn't read all the embedded data.
+        self.set_bit_index(self.settings.prog_code.len() as u64 * 8);
+        self.check_for_password()?;
+        let num_files = if self.container.version >= 2 {
+            self.read_container_file_count()?
+        }
+        else {
+            read_field_number(&mut self.reader(), 3, "number of files")?
+        };
+        Ok(Some(num_files))
+    }
+}
+
+// Method to count the embedded files, as above, where a failure to
+// count them only means that the count isn't known.
+impl Steganography {
+    pub(crate) fn count_embedded_files_or_none(&mut self) -> Option<u64> {
+        self.count_embedded_files().unwrap_or_else(|err| {
+            warn!("Unable to count embedded files: {}", err);
+            None
+        })
+    }
+}
+
+// Read each of the embedded files, from the number of files onwards,
+// and pass them to the sink.
+// Available is the most data that there can be, to check lengths against.
+pub fn extract_entries(data:&mut dyn Read, available:u64, sink:&mut dyn ExtractSink, progress:&mut Progress) -> Result<(), StegError> {
+    // First get the number of files embedded.
+    let num_files = read_field_number(data, 3, "number of files")?;
+    info!("Number of embedded files: {}", num_files);
+    let mut available = available.saturating_sub(3);
+
+    // Let's process each embedded file, one by one.
+    for index in 0..num_files as usize {
+        let (name, metadata, header_len) = read_entry_header(data, index)?;
+
+        // The file contents must all be within the embedded data.
+        available = available.saturating_sub(header_len);
+        if metadata.size > available {
+            error!("File extends past end of embedded data: {}", name);
+            return Err(StegError::CorruptHeader(format!("File data truncated: {}", name)));
+        }
+        available -= metadata.size;
+
+        extract_file(data, &name, &metadata, sink, progress)?;
+    }
+    Ok(())
+}
+
+// Read the header of the next embedded file.
+// Returns the file name, its details, and the length of the header.
+pub fn read_entry_header(data:&mut dyn Read, index:usize) -> Result<(String, EntryMetadata, u64), StegError> {
+    // First get the length of the file name.
+    let file_name_len = read_field_number(data, 3, "file name length")?;
+    info!("File name length: {}", file_name_len);
+
+    // Now that we have the length of the file name we can extract it.
+    let file_name = read_field_text(data, file_name_len, "file name")?;
+    info!("File name: {}", file_name);
+
+    // Only use the name of the file, so that files can't
+    // be written outside of the folder they are extracted to.
+    let file_name = match Path::new(&file_name).file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return Err(StegError::CorruptHeader(format!("Invalid file name: {:?}", file_name))),
+    };
+
+    // Now we need to get the length of the file.
+    let file_len = read_field_number(data, 10, "file length")?;
+    info!("File length: {}", file_len);
+
+    Ok((file_name, EntryMetadata { index, size: file_len }, 3 + file_name_len + 10))
+}
+
+// Extract the contents of an embedded file to a sink.
+// Progress is reported, and cancellation checked, as the sink reads.
+pub fn extract_file(data:&mut dyn Read, name:&str, metadata:&EntryMetadata, sink:&mut dyn ExtractSink, progress:&mut Progress) -> Result<(), StegError> {
+    progress.check_cancelled()?;
+    let mut contents = ProgressReader::new(data.take(metadata.size), name, metadata.size, progress);
+    sink.extract_entry(name, metadata, &mut contents)?;
+
+    // Move on to the end of the file, whether or not the sink
+    // read all of it, checking that all of it was there.
+    io::copy(&mut contents, &mut io::sink())?;
+    if contents.into_inner().limit() != 0 {
+        error!("File data ends early: {}", name);
+        return Err(StegError::CorruptHeader(format!("File data truncated: {}", name)));
+    }
+    Ok(())
+}
+
+// Read embedded header fields.
+// Header fields are fixed length strings, with numbers
+// formatted with leading zeros.
+pub fn read_field_text(data:&mut dyn Read, bytes_to_read:u64, field:&str) -> Result<String, StegError> {
+    let mut bytes = Vec::with_capacity(bytes_to_read as usize);
+    data.take(bytes_to_read).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != bytes_to_read {
+        error!("Expected bytes: {}, bytes read: {}", bytes_to_read, bytes.len());
+        return Err(StegError::CorruptHeader(format!("Truncated {}", field)));
+    }
+    String::from_utf8(bytes)
+        .map_err(|_| StegError::CorruptHeader(format!("Invalid {}", field)))
+}
+
+pub fn read_field_number(data:&mut dyn Read, bytes_to_read:u64, field:&str) -> Result<u64, StegError> {
+    let string = read_field_text(data, bytes_to_read, field)?;
+    string.parse()
+        .map_err(|_| StegError::CorruptHeader(format!("Invalid {}: {:?}", field, string)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::steg::embed::PayloadEntry;
+    use crate::steg::options::{Compression, EmbedOptions, Traversal};
+    use crate::steg::testing::{loaded_steg, test_folder};
+
+    const FILES:[(&str, &[u8]); 3] = [("a.txt", b"first file"), ("b.bin", &[0, 1, 2, 255, 254]), ("c", b"")];
+
+    // Image with the test files embedded, in the original format
+    // or in a versioned container.
+    fn coded_steg(container:bool) -> Steganography {
+        let mut steg = loaded_steg(100, 100);
+        let options = match container {
+            true => EmbedOptions::builder().compression(Compression::Deflate).traversal(Traversal::Permuted).build().unwrap(),
+            false => EmbedOptions::from_settings(&steg.settings),
+        };
+        let entries = FILES.iter().map(|(name, bytes)| PayloadEntry::from_bytes(name, bytes)).collect();
+        steg.embed(entries, &options).unwrap();
+        steg
+    }
+
+    #[test]
+    fn extract_to_memory_returns_embedded_files() {
+        for container in [false, true] {
+            let mut steg = coded_steg(container);
+            let files = steg.extract_to_memory("").unwrap();
+            assert_eq!(files.len(), FILES.len());
+            for (idx, (file, (name, bytes))) in files.iter().zip(FILES).enumerate() {
+                assert_eq!(file.name, name);
+                assert_eq!(file.bytes, bytes);
+                assert_eq!(file.metadata.index, idx);
+                assert_eq!(file.metadata.size, bytes.len() as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn extract_entry_to_writes_one_file() {
+        for container in [false, true] {
+            let mut steg = coded_steg(container);
+
+            let mut out = Vec::new();
+            let metadata = steg.extract_entry_to("", Some("b.bin"), &mut out).unwrap();
+            assert_eq!((metadata.index, out.as_slice()), (1, FILES[1].1));
+
+            // First file if no name is given.
+            out.clear();
+            let metadata = steg.extract_entry_to("", None, &mut out).unwrap();
+            assert_eq!((metadata.index, out.as_slice()), (0, FILES[0].1));
+
+            out.clear();
+            let result = steg.extract_entry_to("", Some("missing"), &mut out);
+            assert!(matches!(result, Err(StegError::EntryNotFound(name)) if name == "missing"));
+            assert!(out.is_empty());
+        }
+    }
+
+    // Existing files are left as they are, and
+    // the extracted file is given a suffix.
+    #[test]
+    fn folder_sink_never_overwrites() {
+        let folder = test_folder("folder-sink");
+        fs::write(folder.join("a.txt"), b"existing").unwrap();
+        fs::write(folder.join("a-001.txt"), b"existing too").unwrap();
+
+        let mut steg = coded_steg(false);
+        let mut sink = FolderSink { folder: folder.clone(), written: Vec::new() };
+        steg.extract_to_sink("", &mut sink).unwrap();
+
+        assert_eq!(fs::read(folder.join("a.txt")).unwrap(), b"existing");
+        assert_eq!(fs::read(folder.join("a-001.txt")).unwrap(), b"existing too");
+        assert_eq!(fs::read(folder.join("a-002.txt")).unwrap(), FILES[0].1);
+        assert_eq!(sink.written, vec![folder.join("a-002.txt"), folder.join("b.bin"), folder.join("c")]);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+// Image cursor position methods.
+//
+// The cursor (row, col, plane, bit) points to the next colour
+// component bit to be read or written.
+// Bits are used a pixel at a time along each row, then row by row,
+// then for each of the rgb colour planes, then for the next bit up
+// in each colour component.
+// This means the cursor can also be held as a single bit index
+// from the start of the image, which is what allows a position in
+// the embedded data to be computed directly from a byte offset.
+
+use crate::steg::Steganography;
+
+use std::thread;
+
+impl Steganography {
+    // Number of pixels in the loaded image.
+    pub fn pic_pixels(&self) -> u64 {
+        self.pic_width as u64 * self.pic_height as u64
+    }
+
+    // Total number of bits that can be read or written
+    // in the loaded image, over all bits of the rgb colour planes.
+    pub fn bit_capacity(&self) -> u64 {
+        self.pic_pixels().saturating_mul(3 * 8)
+    }
+
+    // Bit index of the current cursor position.
+    pub fn bit_index(&self) -> u64 {
+        let pixels = self.pic_pixels();
+        let pixel = self.row as u64 * self.pic_width as u64 + self.col as u64;
+        (self.bit as u64 * 3 + self.plane as u64) * pixels + pixel
+    }
+
+    // Move the cursor to a bit index.
+    pub fn set_bit_index(&mut self, bit_index:u64) {
+        let pixels = self.pic_pixels();
+        if pixels == 0 {
+            return;
+        }
+        let pass = bit_index / pixels;
+        let pixel = bit_index % pixels;
+        self.bit = (pass / 3) as u8;
+        self.plane = (pass % 3) as usize;
+        self.row = (pixel / self.pic_width as u64) as u32;
+        self.col = (pixel % self.pic_width as u64) as u32;
+    }
+}
+
+// Number of threads to use for reading or writing in parallel.
+pub fn worker_threads() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
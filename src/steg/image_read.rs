@@ -1,90 +1,333 @@
 // Image pixel read methods.
 
 use crate::steg::Steganography;
+use crate::steg::image_cursor::worker_threads;
+use crate::steg::traversal::Permutation;
 
-use image::{GenericImageView, Pixel};
+use log::{warn};
+
+use image::DynamicImage;
+use std::thread;
 
 // Method to read a certain number of bytes from an image.
+// Reading works directly on the image sample buffer, stepping
+// through the buffer by a precomputed pixel stride, rather than
+// converting a whole pixel for each bit.
+// Reads of at least par_threshold bytes are done using
+// multiple threads, with identical results.
 impl Steganography {
     pub fn read_data_from_image(&mut self, bytes_to_read:u32) {
 
-        // Initial loop counters.
-        let mut bytes_read:u32 = 0;
-        let mut row_cnt:u32 = self.row;
-        let mut col_cnt:u32 = self.col;
-        let mut col_plane:usize = self.plane;
-        let mut _bits_read:u8 = self.bit;
-        let mut _col_part:u8 = 0;
-        let mut _code_data:u8 = 0;
-        let mut _byte_bit:u8 = 0;
-        let mut _mask:u8 = 0;
-
         // Initialise byte vector for read data.
-        self.code_bytes = Vec::with_capacity(bytes_to_read as usize);
-
-        // Initialise a colour bit mask.
-        // This is so we can read an individual
-        // bit in a pixel colour byte.
-        _mask = 1 << _bits_read;
-
-        // Loop while there are still bytes to read.
-        while bytes_read < bytes_to_read {
-            _code_data = 0;
-
-            // Extract 1 byte of data from image.
-            // one bit at a time.
-            for _idx in 1..9 {
-                // Get the pixel colour for the pixel we are at.
-                if let Some(image) = &self.image {
-                    if self.pic_col_planes == 3 { 
-                        _col_part = image.get_pixel(col_cnt, row_cnt).to_rgb()[col_plane];
-                    } else {
-                        _col_part = image.get_pixel(col_cnt, row_cnt).to_rgba()[col_plane];
-                    }
+        let mut code_bytes = vec![0u8; bytes_to_read as usize];
+
+        // Read the data, and keep the bytes that were read.
+        let bytes_read = self.read_data_into(&mut code_bytes);
+        code_bytes.truncate(bytes_read);
+        self.code_bytes = code_bytes;
+        self.bytes_read = bytes_read as u32;
+    }
+}
+
+// Method to read bytes from an image into a buffer.
+// Reads as many bytes as fit in the buffer, unless the end of
+// the image is reached first.
+// Returns the number of bytes read.
+impl Steganography {
+    pub fn read_data_into(&mut self, out:&mut [u8]) -> usize {
+
+        // Get the cursor position and image dimensions.
+        let start = self.bit_index();
+        let capacity = self.bit_capacity();
+        let pixels = self.pic_pixels() as usize;
+        let par_threshold = self.settings.par_threshold as usize;
+        let permutation = self.permutation;
+
+        // Get the raw sample buffer of the image and the number
+        // of samples (bytes) per pixel.
+        let (samples, stride): (&[u8], usize) = match &self.image {
+            Some(DynamicImage::ImageRgb8(buf)) => (&**buf, 3),
+            Some(DynamicImage::ImageRgba8(buf)) => (&**buf, 4),
+            Some(_) => {
+                warn!("Image not a supported rgb colour type.");
+                return 0;
+            }
+            None => {
+                warn!("No image loaded to read from.");
+                return 0;
+            }
+        };
+        if pixels == 0 {
+            warn!("Image has no pixels to read from.");
+            return 0;
+        }
+
+        // Read the data, in parallel if there is enough of it,
+        // more than one core, and it is all within the image.
+        // Permuted data is read a bit at a time, from wherever each bit is.
+        let threads = worker_threads();
+        let bytes_read = if let Some(permutation) = &permutation {
+            read_samples_permuted(samples, stride, pixels, start, out, permutation)
+        }
+        else if out.len() >= par_threshold && threads > 1 && start + out.len() as u64 * 8 <= capacity {
+            read_samples_par(samples, stride, pixels, start, out, threads)
+        }
+        else {
+            read_samples(samples, stride, pixels, start, out)
+        };
+        if bytes_read != out.len() {
+            warn!("Reached end of image reading data.");
+        }
+
+        // Save the state of the reading.
+        // This allows us to carry on reading from where we
+        // left off on the next chunk of reading.
+        self.set_bit_index(start + bytes_read as u64 * 8);
+        bytes_read
+    }
+}
+
+// Read bytes from the sample buffer of an image,
+// starting from a bit index.
+// Returns the number of whole bytes read.
+pub fn read_samples(samples:&[u8], stride:usize, pixels:usize, start:u64, out:&mut [u8]) -> usize {
+    // Number of samples in the image, across all colour planes.
+    let img_samples = pixels * stride;
+
+    // Initial loop counters.
+    let mut bytes_read:usize = 0;
+    let mut col_plane:usize = ((start / pixels as u64) % 3) as usize;
+    let mut bits_read:u8 = (start / (pixels as u64 * 3)) as u8;
+
+    // Offset into the sample buffer of the colour
+    // plane component we are up to.
+    let mut offset:usize = (start % pixels as u64) as usize * stride + col_plane;
+
+    // Loop while there are still bytes to read,
+    // and bits left in the colour components to read them from.
+    'bytes: for code_byte in out.iter_mut() {
+        if bits_read > 7 {
+            break 'bytes;
+        }
+        let mut code_data:u8 = 0;
+
+        // When the whole byte is well within this colour plane,
+        // read it without checking for the end of the image each bit.
+        if offset + 8 * stride < img_samples {
+            let byte_samples = &samples[offset..offset + 8 * stride];
+            *code_byte = match stride {
+                3 => read_byte::<3>(byte_samples, bits_read),
+                _ => read_byte::<4>(byte_samples, bits_read),
+            };
+            offset += 8 * stride;
+            bytes_read += 1;
+            continue 'bytes;
+        }
+
+        // Extract 1 byte of data from image.
+        // one bit at a time.
+        for idx in (0..8).rev() {
+            // Update the code data bit with the bit from the pixel.
+            code_data = (code_data << 1) | ((samples[offset] >> bits_read) & 1);
+
+            // Next time around we need to point to the same
+            // colour plane of the next pixel.
+            offset += stride;
+
+            // If we have reached the end of the image then go
+            // back to the top and go to the next colour plane.
+            if offset >= img_samples {
+                col_plane += 1;
+                // If we have processed the last plane (colour)
+                // ee go back to the next bit of the first plane,
+                if col_plane == 3 {
+                    col_plane = 0;
+                    bits_read += 1;
                 }
+                offset = col_plane;
+                if bits_read > 7 && idx > 0 {
+                    break 'bytes;
+                }
+            }
+        }
+        // Store the completed byte.
+        *code_byte = code_data;
+
+        // Increment bytes read.
+        bytes_read += 1;
+    }
+    bytes_read
+}
+
+// Read a byte from a colour plane of 8 pixels in a row.
+// The stride is a constant, so that the compiler can unroll the loop.
+#[inline(always)]
+fn read_byte<const STRIDE: usize>(byte_samples:&[u8], bits_read:u8) -> u8 {
+    byte_samples
+        .chunks_exact(STRIDE)
+        .fold(0, |code_data, pixel| (code_data << 1) | ((pixel[0] >> bits_read) & 1))
+}
+
+// Read bytes from the sample buffer of an image, starting from a
+// bit index, with each bit read from its permuted bit index.
+// Returns the number of whole bytes read.
+pub fn read_samples_permuted(samples:&[u8], stride:usize, pixels:usize, start:u64, out:&mut [u8], permutation:&Permutation) -> usize {
+    let capacity = pixels as u64 * 24;
+    let mut bit_index = start;
+    for (bytes_read, code_byte) in out.iter_mut().enumerate() {
+        if bit_index + 8 > capacity {
+            return bytes_read;
+        }
+        let mut code_data:u8 = 0;
+        for _ in 0..8 {
+            let (offset, bits_read) = sample_bit(permutation.map(bit_index), stride, pixels);
+            code_data = (code_data << 1) | ((samples[offset] >> bits_read) & 1);
+            bit_index += 1;
+        }
+        *code_byte = code_data;
+    }
+    out.len()
+}
+
+// Offset into the sample buffer, and bit of the sample, for a bit index.
+pub fn sample_bit(bit_index:u64, stride:usize, pixels:usize) -> (usize, u8) {
+    let pass = bit_index / pixels as u64;
+    let pixel = (bit_index % pixels as u64) as usize;
+    (pixel * stride + (pass % 3) as usize, (pass / 3) as u8)
+}
+
+// Read bytes from the sample buffer of an image using a number of threads.
+// Each thread reads a contiguous run of the bytes, starting from
+// the bit index computed from its byte offset.
+// Caller must check that all the data is within the image.
+pub(crate) fn read_samples_par(samples:&[u8], stride:usize, pixels:usize, start:u64, out:&mut [u8], threads:usize) -> usize {
+    if out.is_empty() {
+        return 0;
+    }
+    let run_len = out.len().div_ceil(threads.max(1));
+
+    thread::scope(|scope| {
+        for (run, run_out) in out.chunks_mut(run_len).enumerate() {
+            scope.spawn(move || {
+                let run_start = start + (run * run_len) as u64 * 8;
+                read_samples(samples, stride, pixels, run_start, run_out);
+            });
+        }
+    });
+    out.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+    use crate::steg::testing::noise;
+
+    use image::{GenericImageView, Pixel, RgbImage, RgbaImage};
+
+    // Cursor as row, col, plane and bit.
+    type Cursor = (u32, u32, usize, u8);
 
-                // Update the code data bit with the bit from the pixel.
-                _byte_bit = _col_part & _mask;
-                _byte_bit = _byte_bit >> _bits_read;
-                _code_data = _code_data << 1;
-                _code_data = _code_data | _byte_bit;
-
-                // Next time around we need to point to the next pixel in the row.
-                col_cnt = col_cnt + 1;
-                // Until we get to the end of the row.
-                // Then move to the start of the next row.
-                if col_cnt == self.pic_width {
-                    col_cnt = 0;
-                    row_cnt = row_cnt + 1;
-                    // If we have reached the end of the image then go
-                    // back to the top and go to the next bit.
-                    if row_cnt == self.pic_height {
-                        row_cnt = 0;
-                        col_plane = col_plane + 1;
-                        // If we have processed the last plane (colour)
-                        // ee go back to the next bit of the first plane,
-                        if col_plane == 3 {
-                            col_plane = 0;
-                            _bits_read = _bits_read + 1;
-                            _mask = _mask << 1;
+    // Original read algorithm, getting a whole pixel for each bit,
+    // as the sample buffer reads must match it exactly.
+    fn read_per_pixel(image:&DynamicImage, cursor:Cursor, len:usize) -> (Vec<u8>, Cursor) {
+        let (width, height) = image.dimensions();
+        let (mut row, mut col, mut plane, mut bit) = cursor;
+        let mut bytes = Vec::with_capacity(len);
+        for _ in 0..len {
+            let mut byte = 0u8;
+            for _ in 0..8 {
+                let rgb = image.get_pixel(col, row).to_rgb();
+                byte = (byte << 1) | ((rgb[plane] >> bit) & 1);
+                col += 1;
+                if col == width {
+                    col = 0;
+                    row += 1;
+                    if row == height {
+                        row = 0;
+                        plane += 1;
+                        if plane == 3 {
+                            plane = 0;
+                            bit += 1;
                         }
                     }
                 }
             }
-            // Push the completed byte into the byte vector.
-            self.code_bytes.push(_code_data);
+            bytes.push(byte);
+        }
+        (bytes, (row, col, plane, bit))
+    }
 
-            // Increment bytes read.
-            bytes_read = bytes_read + 1;
+    fn loaded_steg(image:DynamicImage) -> Steganography {
+        // Single threaded, whatever the size of the reads.
+        let settings = Settings { par_threshold: u32::MAX, ..Settings::default() };
+        let mut steg = Steganography::new(settings);
+        (steg.pic_width, steg.pic_height) = image.dimensions();
+        steg.pic_col_planes = image.color().channel_count();
+        steg.image = Some(image);
+        steg.img_to_proc = true;
+        steg
+    }
+
+    // Read the whole image, in reads of odd sizes, so that reads start and
+    // end part way through rows, colour planes and bit passes, checking
+    // the bytes and cursor against the original algorithm after each.
+    fn check_matches_per_pixel(image:DynamicImage) {
+        let mut steg = loaded_steg(image.clone());
+        let capacity = (steg.bit_capacity() / 8) as usize;
+        let mut cursor:Cursor = (0, 0, 0, 0);
+        let mut read = 0;
+        for len in [1, 3, 5, 2, 11, 4, 7].iter().cycle() {
+            if read == capacity {
+                break;
+            }
+            let len = (*len).min(capacity - read);
+            let mut out = vec![0u8; len];
+            assert_eq!(steg.read_data_into(&mut out), len);
+            let expected;
+            (expected, cursor) = read_per_pixel(&image, cursor, len);
+            assert_eq!(out, expected);
+            assert_eq!((steg.row, steg.col, steg.plane, steg.bit), cursor);
+            read += len;
         }
 
-        // Save the state of the reading.
-        // This allows us to carry on reading from where we
-        // left off on the next chunk of reading.
-        self.row = row_cnt;
-        self.col = col_cnt;
-        self.plane = col_plane;
-        self.bit = _bits_read;
-        self.bytes_read = bytes_read;
+        // Every bit has been read, so nothing more can be read.
+        assert_eq!(steg.read_data_into(&mut [0u8; 1]), 0);
+    }
+
+    #[test]
+    fn rgb_reads_match_per_pixel_reads() {
+        let samples = noise(7 * 5 * 3, 4);
+        check_matches_per_pixel(DynamicImage::ImageRgb8(RgbImage::from_raw(7, 5, samples).unwrap()));
+    }
+
+    #[test]
+    fn rgba_reads_match_per_pixel_reads() {
+        // Alpha samples are never read.
+        let samples = noise(6 * 4 * 4, 5);
+        check_matches_per_pixel(DynamicImage::ImageRgba8(RgbaImage::from_raw(6, 4, samples).unwrap()));
+    }
+
+    // Parallel reads must match single threaded reads, for any number
+    // of threads, wherever the reads start and end, and however the
+    // bytes split into runs.
+    #[test]
+    fn parallel_reads_match_single_threaded_reads() {
+        let pixels = 9 * 13;
+        let capacity = pixels as u64 * 24;
+        for stride in [3, 4] {
+            let samples:Vec<u8> = (0..pixels * stride).map(|idx| (idx * 97 + idx / 5) as u8).collect();
+            for start in [0, 5, pixels as u64 - 3, pixels as u64 * 2 + 17, pixels as u64 * 7 + 1] {
+                for len in [1, 9, 40, ((capacity - start) / 8) as usize] {
+                    let mut expected = vec![0u8; len];
+                    assert_eq!(read_samples(&samples, stride, pixels, start, &mut expected), len);
+                    for threads in [1, 2, 3, 7] {
+                        let mut out = vec![0u8; len];
+                        assert_eq!(read_samples_par(&samples, stride, pixels, start, &mut out, threads), len);
+                        assert!(out == expected, "stride: {}, start: {}, len: {}, threads: {}", stride, start, len, threads);
+                    }
+                }
+            }
+        }
     }
 }
@@ -1,112 +1,365 @@
 // Image pixel write methods.
 
 use crate::steg::Steganography;
+use crate::steg::image_cursor::worker_threads;
+use crate::steg::image_read::sample_bit;
+use crate::steg::traversal::Permutation;
 
 use log::{warn};
 
-use image::{GenericImageView, Pixel};
-use crate::steg::image::GenericImage;
+use image::DynamicImage;
+use std::thread;
 
 // Method to writw a certain number of bytes to am image.
 // Data is written to the image a chunk at a time.
-// Only dealing with rgb image files.
-// Expectation is that rgba documents will be converted to
-// rgb format before embedding data.
+// Only dealing with rgb image files, although the rgb bytes
+// of rgba images are also written to.
+// Writing works directly on the image sample buffer, stepping
+// through the buffer by a precomputed pixel stride, rather than
+// reading and writing whole pixels for each bit.
+// Chunks of at least par_threshold bytes are written using
+// multiple threads, with identical results.
 impl Steganography {
     pub fn write_data_to_image(&mut self, bytes:&[u8]) -> u32 {
 
-        // Initial loop counters.
-        let mut bytes_written:u32 = 0;
-        let mut row_cnt:u32 = self.row;
-        let mut col_cnt:u32 = self.col;
-        let mut col_plane:usize = self.plane;
-        let mut bit_write:u8 = self.bit;
- 
-        let mut col_part:image::Rgb<u8>;
-        let mut _mask:u8 = 0;
-        let mut _col_mask:u8 = 0;
-        let mut _mapped_bit:u8 = 0;
-
-        // Intialise colour bit mask.
-        _col_mask = 1 << bit_write;
-
-        for byte_data in bytes {
-            // Mask for reading byte bits.
-            // Start from MSB so in bit order in the image (assume 8 bit byte).
-            _mask = 128;
-
-            // Extract 1 byte of data from image.
-            // one bit at a time.
-            for _idx in 1..9 {
-                // Get next bit of the byte in the array.
-                if (byte_data & _mask) == 0{
-                    _mapped_bit = 0;
+        // Get the cursor position and image dimensions
+        // before borrowing the image buffer.
+        let start = self.bit_index();
+        let capacity = self.bit_capacity();
+        let width = self.pic_width as usize;
+        let pixels = self.pic_pixels() as usize;
+        let par_threshold = self.settings.par_threshold as usize;
+        let permutation = self.permutation;
+
+        // Get the raw sample buffer of the image and the number
+        // of samples (bytes) per pixel.
+        let (samples, stride): (&mut [u8], usize) = match self.image.as_mut() {
+            Some(DynamicImage::ImageRgb8(buf)) => (&mut **buf, 3),
+            Some(DynamicImage::ImageRgba8(buf)) => (&mut **buf, 4),
+            Some(_) => {
+                warn!("Image not a supported rgb colour type.");
+                return 0;
+            }
+            None => {
+                warn!("No image loaded to write to.");
+                return 0;
+            }
+        };
+        if pixels == 0 {
+            warn!("Image has no pixels to write to.");
+            return 0;
+        }
+
+        // Write the data, in parallel if there is enough of it,
+        // more than one core, and it all fits in the image.
+        // Permuted data is written a bit at a time, to wherever each bit goes.
+        let threads = worker_threads();
+        let bytes_written = if let Some(permutation) = &permutation {
+            write_samples_permuted(samples, stride, pixels, start, bytes, permutation)
+        }
+        else if bytes.len() >= par_threshold && threads > 1 && start + bytes.len() as u64 * 8 <= capacity {
+            write_samples_par(samples, stride, width, pixels, start, bytes, threads)
+        }
+        else {
+            write_samples(samples, stride, pixels, start, bytes)
+        };
+        if bytes_written != bytes.len() {
+            warn!("Reached end of image writing data.");
+        }
+
+        // Save the state of the writing.
+        self.set_bit_index(start + bytes_written as u64 * 8);
+
+        // Return the number of bytes written for
+        // comparison by caller.
+        bytes_written as u32
+    }
+}
+
+// Write bytes into the sample buffer of an image,
+// starting from a bit index.
+// Returns the number of whole bytes written.
+pub fn write_samples(samples:&mut [u8], stride:usize, pixels:usize, start:u64, bytes:&[u8]) -> usize {
+    // Number of samples in the image, across all colour planes.
+    let img_samples = pixels * stride;
+
+    // Initial loop counters.
+    let mut bytes_written:usize = 0;
+    let mut col_plane:usize = ((start / pixels as u64) % 3) as usize;
+    let mut bit_write:u8 = (start / (pixels as u64 * 3)) as u8;
+
+    // Offset into the sample buffer of the colour
+    // plane component we are up to.
+    let mut offset:usize = (start % pixels as u64) as usize * stride + col_plane;
+
+    // Intialise colour bit mask.
+    let mut col_mask:u8 = 1 << (bit_write & 7);
+
+    'bytes: for byte_data in bytes {
+        // Can't write past the last bit of the colour components.
+        if bit_write > 7 {
+            break 'bytes;
+        }
+
+        // When the whole byte is well within this colour plane,
+        // write it without checking for the end of the image each bit.
+        if offset + 8 * stride < img_samples {
+            let byte_samples = &mut samples[offset - col_plane..offset - col_plane + 8 * stride];
+            match stride {
+                3 => write_byte::<3>(byte_samples, col_plane, bit_write, *byte_data),
+                _ => write_byte::<4>(byte_samples, col_plane, bit_write, *byte_data),
+            }
+            offset += 8 * stride;
+            bytes_written += 1;
+            continue 'bytes;
+        }
+
+        // Embed 1 byte of data into the image.
+        // one bit at a time.
+        // Start from MSB so in bit order in the image (assume 8 bit byte).
+        for shift in (0..8).rev() {
+            // Get next bit of the byte in the array,
+            // mapped to the bit position we are writing.
+            let mapped_bit:u8 = ((byte_data >> shift) & 1) << bit_write;
+
+            // Modify the colour plane component that we are up to.
+            samples[offset] = (samples[offset] & !col_mask) | mapped_bit;
+
+            // Pixels written to are set to no transparency,
+            // as has always been the case for rgba images.
+            if stride == 4 {
+                samples[offset - col_plane + 3] = 255;
+            }
+
+            // Point to the same colour plane of the next pixel.
+            offset += stride;
+
+            // If we have reached the end of the image then go
+            // back to the top and go to the next colour plane.
+            if offset >= img_samples {
+                col_plane += 1;
+                if col_plane == 3 {
+                    col_plane = 0;
+                    // Used all colour planes so move to next bit.
+                    bit_write += 1;
+                    col_mask <<= 1;
                 }
-                else {
-                    _mapped_bit = 1;
+                offset = col_plane;
+                if bit_write > 7 && shift > 0 {
+                    break 'bytes;
                 }
-                _mapped_bit = _mapped_bit << bit_write;
-
-                // Get the pixel colour for the pixel we are at.
-                if let Some(mut img) = self.image.take() {
-                    col_part = img.get_pixel(col_cnt, row_cnt).to_rgb();
-
-                    // Modify the colour plane component that we are up to.
-                    // First get all the colour parts
-                    let mut r = col_part[0];
-                    let mut g = col_part[1];
-                    let mut b = col_part[2];
-                    match col_plane {
-                        0 => {r = (r & (! _col_mask)) + _mapped_bit},
-                        1 => {g = (g & (! _col_mask)) + _mapped_bit},
-                        2 => {b = (b & (! _col_mask)) + _mapped_bit},
-                        _ => warn!("Unexpected colour plane."),
+            }
+        }
+        // Increment characters writen counter.
+        bytes_written += 1;
+    }
+    bytes_written
+}
+
+// Write a byte to a colour plane of 8 pixels in a row.
+// The stride is a constant, so that the compiler can unroll the loop.
+#[inline(always)]
+fn write_byte<const STRIDE: usize>(byte_samples:&mut [u8], col_plane:usize, bit_write:u8, byte_data:u8) {
+    let col_mask:u8 = 1 << bit_write;
+    for (shift, pixel) in (0..8).rev().zip(byte_samples.chunks_exact_mut(STRIDE)) {
+        let mapped_bit:u8 = ((byte_data >> shift) & 1) << bit_write;
+        pixel[col_plane] = (pixel[col_plane] & !col_mask) | mapped_bit;
+        // Pixels written to are set to no transparency.
+        if STRIDE == 4 {
+            pixel[3] = 255;
+        }
+    }
+}
+
+// Write bytes into the sample buffer of an image, starting from a
+// bit index, with each bit written to its permuted bit index.
+// Returns the number of whole bytes written.
+pub fn write_samples_permuted(samples:&mut [u8], stride:usize, pixels:usize, start:u64, bytes:&[u8], permutation:&Permutation) -> usize {
+    let capacity = pixels as u64 * 24;
+    let mut bit_index = start;
+    for (bytes_written, byte_data) in bytes.iter().enumerate() {
+        if bit_index + 8 > capacity {
+            return bytes_written;
+        }
+        for shift in (0..8).rev() {
+            let (offset, bit_write) = sample_bit(permutation.map(bit_index), stride, pixels);
+            let mapped_bit:u8 = ((byte_data >> shift) & 1) << bit_write;
+            samples[offset] = (samples[offset] & !(1 << bit_write)) | mapped_bit;
+            // Pixels written to are set to no transparency.
+            if stride == 4 {
+                samples[offset - offset % stride + 3] = 255;
+            }
+            bit_index += 1;
+        }
+    }
+    bytes.len()
+}
+
+// Write bytes into the sample buffer of an image using a number of threads.
+// Each thread owns a band of image rows, and writes every bit of
+// the data that lands in its rows, for each colour plane and bit pass.
+// Caller must check that all the data fits in the image.
+pub(crate) fn write_samples_par(samples:&mut [u8], stride:usize, width:usize, pixels:usize, start:u64, bytes:&[u8], threads:usize) -> usize {
+    let plane_bits = pixels as u64;
+    let end = start + bytes.len() as u64 * 8;
+    if end == start {
+        return 0;
+    }
+
+    // Split the image into bands of whole rows, one per thread.
+    let rows = pixels / width;
+    let band_rows = rows.div_ceil(threads.max(1));
+
+    thread::scope(|scope| {
+        for (band, band_samples) in samples.chunks_mut(band_rows * width * stride).enumerate() {
+            scope.spawn(move || {
+                // Range of pixels in this band.
+                let first_pixel = (band * band_rows * width) as u64;
+                let last_pixel = first_pixel + (band_samples.len() / stride) as u64;
+
+                // Go through each pass over the image that the data covers.
+                for pass in (start / plane_bits)..=((end - 1) / plane_bits) {
+                    let col_plane = (pass % 3) as usize;
+                    let bit_write = (pass / 3) as u8;
+                    let col_mask:u8 = 1 << bit_write;
+
+                    // Bits of this pass that land in this band.
+                    let pass_start = pass * plane_bits;
+                    let lo = start.max(pass_start + first_pixel);
+                    let hi = end.min(pass_start + last_pixel);
+                    if lo >= hi {
+                        continue;
                     }
 
-                    // Update the pixel colour now that the colour component has been modified.
-                    // Note that the image method works with rgba,
-                    // So we set a to 255 (no transparency).
-                    let modified_pixel = image::Rgba([r, g, b, 255]);
-                    img.put_pixel(col_cnt, row_cnt, modified_pixel);
-                    self.image = Some(img);
+                    let mut offset = (lo - pass_start - first_pixel) as usize * stride + col_plane;
+                    for data_bit in (lo - start)..(hi - start) {
+                        let byte_data = bytes[(data_bit / 8) as usize];
+                        let mapped_bit:u8 = ((byte_data >> (7 - data_bit % 8)) & 1) << bit_write;
+                        band_samples[offset] = (band_samples[offset] & !col_mask) | mapped_bit;
+                        if stride == 4 {
+                            band_samples[offset - col_plane + 3] = 255;
+                        }
+                        offset += stride;
+                    }
                 }
+            });
+        }
+    });
+    bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+    use crate::steg::testing::noise;
+
+    use image::{GenericImage, GenericImageView, Pixel, RgbImage, RgbaImage};
 
-                // Shift mask right (towards LSB).
-                _mask = _mask >> 1;
-    
-                // Point to next column.
-                col_cnt = col_cnt + 1;
-                if col_cnt == self.pic_width {
-                    col_cnt = 0;
-                    row_cnt = row_cnt + 1;
-                    // If we have reached the end of the image then go
-                    // back to the top and go to the next bit.
-                    if row_cnt == self.pic_height {
-                        row_cnt = 0;
-                        // Point to the next colour plane.
-                        // Take into account number of planes.
-                        col_plane = col_plane + 1;
-                        if col_plane == 3 {
-                            col_plane = 0;
-                            // Used all colour planes so move to next bit.
-                            bit_write = bit_write + 1;
-                            _col_mask = _col_mask << 1;
-                        }     
+    // Cursor as row, col, plane and bit.
+    type Cursor = (u32, u32, usize, u8);
+
+    // Original write algorithm, getting and putting a whole pixel for
+    // each bit, as the sample buffer writes must match it exactly.
+    fn write_per_pixel(image:&mut DynamicImage, cursor:Cursor, bytes:&[u8]) -> Cursor {
+        let (width, height) = image.dimensions();
+        let (mut row, mut col, mut plane, mut bit) = cursor;
+        for byte in bytes {
+            for shift in (0..8).rev() {
+                let mut rgb = image.get_pixel(col, row).to_rgb();
+                rgb[plane] = (rgb[plane] & !(1 << bit)) | (((byte >> shift) & 1) << bit);
+                image.put_pixel(col, row, image::Rgba([rgb[0], rgb[1], rgb[2], 255]));
+                col += 1;
+                if col == width {
+                    col = 0;
+                    row += 1;
+                    if row == height {
+                        row = 0;
+                        plane += 1;
+                        if plane == 3 {
+                            plane = 0;
+                            bit += 1;
+                        }
                     }
                 }
             }
-            // Increment characters writen counter.
-            bytes_written = bytes_written + 1;
         }
+        (row, col, plane, bit)
+    }
 
-        // Save the state of the reading.
-        self.row = row_cnt;
-        self.col = col_cnt;
-        self.plane = col_plane;
-        self.bit = bit_write;
+    fn loaded_steg(image:DynamicImage) -> Steganography {
+        // Single threaded, whatever the size of the writes.
+        let settings = Settings { par_threshold: u32::MAX, ..Settings::default() };
+        let mut steg = Steganography::new(settings);
+        (steg.pic_width, steg.pic_height) = image.dimensions();
+        steg.pic_col_planes = image.color().channel_count();
+        steg.image = Some(image);
+        steg.img_to_proc = true;
+        steg
+    }
 
-        // Return the number of bytes written for
-        // comparison by caller.
-        return bytes_written;
+    // Fill the image, in writes of odd sizes, so that writes start and
+    // end part way through rows, colour planes and bit passes, checking
+    // the image and cursor against the original algorithm after each.
+    fn check_matches_per_pixel(image:DynamicImage) {
+        let mut expected = image.clone();
+        let mut steg = loaded_steg(image);
+        let data = noise((steg.bit_capacity() / 8) as usize, 1);
+        let mut cursor:Cursor = (0, 0, 0, 0);
+        let mut written = 0;
+        for len in [1, 3, 5, 2, 11, 4, 7].iter().cycle() {
+            if written == data.len() {
+                break;
+            }
+            let chunk = &data[written..(written + len).min(data.len())];
+            assert_eq!(steg.write_data_to_image(chunk), chunk.len() as u32);
+            cursor = write_per_pixel(&mut expected, cursor, chunk);
+            assert_eq!(steg.image.as_ref().unwrap().as_bytes(), expected.as_bytes());
+            assert_eq!((steg.row, steg.col, steg.plane, steg.bit), cursor);
+            written += chunk.len();
+        }
+
+        // Every bit has been used, so nothing more can be written.
+        assert_eq!(steg.write_data_to_image(&[0xff]), 0);
+        assert_eq!(steg.image.as_ref().unwrap().as_bytes(), expected.as_bytes());
+    }
+
+    #[test]
+    fn rgb_writes_match_per_pixel_writes() {
+        let samples = noise(7 * 5 * 3, 2);
+        check_matches_per_pixel(DynamicImage::ImageRgb8(RgbImage::from_raw(7, 5, samples).unwrap()));
+    }
+
+    #[test]
+    fn rgba_writes_match_per_pixel_writes() {
+        // Alpha samples aren't all opaque, to check that written pixels are made opaque.
+        let samples = noise(6 * 4 * 4, 3);
+        check_matches_per_pixel(DynamicImage::ImageRgba8(RgbaImage::from_raw(6, 4, samples).unwrap()));
+    }
+
+    // Parallel writes must match single threaded writes, for any number
+    // of threads, wherever the writes start and end, and however the
+    // image rows split into bands.
+    #[test]
+    fn parallel_writes_match_single_threaded_writes() {
+        // 13 rows don't split evenly into bands for any of the thread counts.
+        let (width, height) = (9, 13);
+        let pixels = width * height;
+        let capacity = pixels as u64 * 24;
+        for stride in [3, 4] {
+            let image_samples = noise(pixels * stride, stride as u32);
+            for start in [0, 5, pixels as u64 - 3, pixels as u64 * 2 + 17, pixels as u64 * 7 + 1] {
+                for len in [1, 9, 40, ((capacity - start) / 8) as usize] {
+                    let bytes = noise(len, start as u32 + len as u32);
+                    let mut expected = image_samples.clone();
+                    assert_eq!(write_samples(&mut expected, stride, pixels, start, &bytes), len);
+                    for threads in [1, 2, 3, 7] {
+                        let mut samples = image_samples.clone();
+                        assert_eq!(write_samples_par(&mut samples, stride, width, pixels, start, &bytes, threads), len);
+                        assert!(samples == expected, "stride: {}, start: {}, len: {}, threads: {}", stride, start, len, threads);
+                    }
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,251 @@
+// Embedding options.
+//
+// Options are put together with a builder, which checks that
+// the combination of options makes sense before anything is embedded.
+// Whether the data then fits in a particular image is checked
+// when embedding, before any pixels are touched.
+
+use crate::password::Password;
+use crate::settings::Settings;
+use crate::steg::StegError;
+
+use std::path::Path;
+use zeroize::Zeroizing;
+
+// Default number of PBKDF2 iterations for deriving keys from passwords.
+pub const DEFAULT_KDF_ITERATIONS: u32 = 100_000;
+
+// Encryption of the embedded data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encryption {
+    None,
+    // ChaCha20-Poly1305 with a key derived from the password.
+    ChaCha20Poly1305,
+}
+
+// Compression of the embedded data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Deflate,
+}
+
+// Order in which the embedded data uses the image colour bits.
+// Sequential is the order of the original format: a pixel at a time
+// along each row, then row by row, then for each rgb colour plane,
+// then for the next bit up in each colour component.
+// Permuted scatters the container data over the image, in an order
+// from a seed recorded in the container header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Traversal {
+    Sequential,
+    Permuted,
+}
+
+// Forward error correction of the embedded data.
+// Each block of data is repeated, and read back by majority vote,
+// so that a few changed bits in an image don't lose the data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FecLevel {
+    None,
+    Repeat3,
+    Repeat5,
+}
+
+impl FecLevel {
+    // Number of copies of each block of data.
+    pub fn copies(self) -> u8 {
+        match self {
+            FecLevel::None => 1,
+            FecLevel::Repeat3 => 3,
+            FecLevel::Repeat5 => 5,
+        }
+    }
+
+    pub fn from_copies(copies: u8) -> Option<Self> {
+        match copies {
+            1 => Some(FecLevel::None),
+            3 => Some(FecLevel::Repeat3),
+            5 => Some(FecLevel::Repeat5),
+            _ => None,
+        }
+    }
+}
+
+// Lossless image formats that embedded images can be saved as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Png,
+    Bmp,
+    Tiff,
+}
+
+impl OutputFormat {
+    // Output format for a file, from its extension.
+    // Lossy formats are refused as they would destroy the embedded data.
+    pub fn from_path(path: &str) -> Result<Self, StegError> {
+        let extension = Path::new(path)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if extension.is_empty() {
+            return Err(StegError::InvalidOptions(format!("No image format extension on: {}", path)));
+        }
+        OutputFormat::from_extension(&extension).map_err(|_| {
+            StegError::InvalidOptions(format!("Not a lossless image format (png, bmp or tiff): {}", path))
+        })
+    }
+
+    // Output format from a file extension, or format name.
+    pub fn from_extension(extension: &str) -> Result<Self, StegError> {
+        match extension.to_lowercase().as_str() {
+            "png" => Ok(OutputFormat::Png),
+            "bmp" => Ok(OutputFormat::Bmp),
+            "tif" | "tiff" => Ok(OutputFormat::Tiff),
+            _ => Err(StegError::InvalidOptions(format!(
+                "Not a lossless image format (png, bmp or tiff): {}",
+                extension
+            ))),
+        }
+    }
+
+    pub fn image_format(self) -> image::ImageFormat {
+        match self {
+            OutputFormat::Png => image::ImageFormat::Png,
+            OutputFormat::Bmp => image::ImageFormat::Bmp,
+            OutputFormat::Tiff => image::ImageFormat::Tiff,
+        }
+    }
+}
+
+// Options for embedding data in an image.
+#[derive(Debug, Clone)]
+pub struct EmbedOptions {
+    pub password: Option<Password>,
+    pub encryption: Encryption,
+    pub compression: Compression,
+    pub traversal: Traversal,
+    pub bits_per_channel: u8,
+    pub fec_level: FecLevel,
+    pub kdf_iterations: u32,
+    // Format to save the embedded image in, or None for the
+    // format of the file extension it is saved with.
+    pub output_format: Option<OutputFormat>,
+}
+
+impl EmbedOptions {
+    pub fn builder() -> EmbedOptionsBuilder {
+        EmbedOptionsBuilder::default()
+    }
+
+    // Options with the bits per channel from the settings
+    // maximum embedding ratio, and nothing else set.
+    pub fn from_settings(settings: &Settings) -> Self {
+        EmbedOptionsBuilder::from_settings(settings).options
+    }
+
+    // Whether the options need the versioned container format,
+    // rather than the original pic coded format.
+    pub fn needs_container(&self) -> bool {
+        self.encryption != Encryption::None
+            || self.compression != Compression::None
+            || self.fec_level != FecLevel::None
+            || self.traversal != Traversal::Sequential
+    }
+}
+
+impl Default for EmbedOptions {
+    fn default() -> Self {
+        EmbedOptions::from_settings(&Settings::default())
+    }
+}
+
+// Builder for embedding options.
+#[derive(Debug, Clone)]
+pub struct EmbedOptionsBuilder {
+    options: EmbedOptions,
+}
+
+impl Default for EmbedOptionsBuilder {
+    fn default() -> Self {
+        EmbedOptionsBuilder::from_settings(&Settings::default())
+    }
+}
+
+impl EmbedOptionsBuilder {
+    // Builder starting from the bits per channel implied by the
+    // settings maximum embedding ratio (a ratio of 0.5 is 4 bits).
+    pub fn from_settings(settings: &Settings) -> Self {
+        let bits_per_channel = (settings.max_embed_ratio * 8.0).round().clamp(1.0, 8.0) as u8;
+        EmbedOptionsBuilder {
+            options: EmbedOptions {
+                password: None,
+                encryption: Encryption::None,
+                compression: Compression::None,
+                traversal: Traversal::Sequential,
+                bits_per_channel,
+                fec_level: FecLevel::None,
+                kdf_iterations: DEFAULT_KDF_ITERATIONS,
+                output_format: None,
+            },
+        }
+    }
+
+    pub fn password(mut self, password: &str) -> Self {
+        self.options.password = Some(Zeroizing::new(password.to_string()));
+        self
+    }
+
+    pub fn encryption(mut self, encryption: Encryption) -> Self {
+        self.options.encryption = encryption;
+        self
+    }
+
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.options.compression = compression;
+        self
+    }
+
+    pub fn traversal(mut self, traversal: Traversal) -> Self {
+        self.options.traversal = traversal;
+        self
+    }
+
+    pub fn bits_per_channel(mut self, bits_per_channel: u8) -> Self {
+        self.options.bits_per_channel = bits_per_channel;
+        self
+    }
+
+    pub fn fec_level(mut self, fec_level: FecLevel) -> Self {
+        self.options.fec_level = fec_level;
+        self
+    }
+
+    pub fn kdf_iterations(mut self, kdf_iterations: u32) -> Self {
+        self.options.kdf_iterations = kdf_iterations;
+        self
+    }
+
+    pub fn output_format(mut self, output_format: OutputFormat) -> Self {
+        self.options.output_format = Some(output_format);
+        self
+    }
+
+    // Check the combination of options, and return them if valid.
+    pub fn build(self) -> Result<EmbedOptions, StegError> {
+        let options = self.options;
+        if !(1..=8).contains(&options.bits_per_channel) {
+            return Err(StegError::InvalidOptions(format!(
+                "Bits per channel must be from 1 to 8, not {}",
+                options.bits_per_channel
+            )));
+        }
+        if options.encryption != Encryption::None && options.password.as_ref().is_none_or(|password| password.is_empty()) {
+            return Err(StegError::InvalidOptions(String::from("Encryption requires a password")));
+        }
+        if options.password.is_some() && options.needs_container() && options.kdf_iterations == 0 {
+            return Err(StegError::InvalidOptions(String::from("Key derivation iterations must be more than 0")));
+        }
+        Ok(options)
+    }
+}
@@ -0,0 +1,125 @@
+// Embedding preview methods.
+//
+// An embedded image is compared with the image as it was before
+// embedding, sample by sample, to show how many colour samples were
+// changed, and where, before the embedded image is saved.
+// The rgb samples hold the embedded data, but alpha samples are
+// compared too, as embedding makes every pixel it writes to opaque.
+
+use crate::steg::{StegError, Steganography};
+
+use image::{DynamicImage, RgbImage};
+use log::info;
+
+// Differences between an image before and after embedding.
+pub struct ImageDifference {
+    // Number of samples that were changed, alpha samples included.
+    pub changed_samples: u64,
+    // Number of samples in the image, alpha samples included
+    // if either image has them.
+    pub total_samples: u64,
+    // Number of alpha samples that were changed.
+    pub changed_alpha: u64,
+    // Changed samples, brightened so that the smallest change shows.
+    // A changed alpha sample shows in all three colours of its pixel.
+    // Unchanged samples are black.
+    pub heatmap: RgbImage,
+}
+
+impl ImageDifference {
+    // Percentage of the samples that were changed.
+    pub fn changed_percent(&self) -> f64 {
+        if self.total_samples == 0 {
+            return 0.0;
+        }
+        self.changed_samples as f64 * 100.0 / self.total_samples as f64
+    }
+}
+
+// Method to load the loaded image file as it is on disk, which is
+// the image before any embedding since it was loaded or saved.
+impl Steganography {
+    pub fn load_saved_image(&self) -> Result<DynamicImage, StegError> {
+        if !self.img_to_proc {
+            return Err(StegError::NoImage);
+        }
+        info!("Loading saved image for comparison: {}", self.image_file);
+        image::open(&self.image_file).map_err(|err| match err {
+            image::ImageError::IoError(io_err) => StegError::Io(io_err),
+            err => StegError::Open(err),
+        })
+    }
+}
+
+// Compare an image before and after embedding.
+pub fn image_difference(cover:&DynamicImage, stego:&DynamicImage) -> Result<ImageDifference, StegError> {
+    let has_alpha = cover.color().has_alpha() || stego.color().has_alpha();
+    let cover = cover.to_rgba8();
+    let stego = stego.to_rgba8();
+    if cover.dimensions() != stego.dimensions() {
+        return Err(StegError::SizeMismatch { expected: cover.dimensions(), found: stego.dimensions() });
+    }
+
+    // Changes are scaled to the largest change, so that changes to
+    // the lowest bits are as visible as changes to higher bits.
+    let largest_change = cover.iter().zip(stego.iter()).map(|(before, after)| before.abs_diff(*after)).max().unwrap_or(0);
+
+    let (width, height) = cover.dimensions();
+    let mut heatmap = RgbImage::new(width, height);
+    let mut changed_samples:u64 = 0;
+    let mut changed_alpha:u64 = 0;
+    for ((heat, before), after) in heatmap.pixels_mut().zip(cover.pixels()).zip(stego.pixels()) {
+        let alpha_change = before[3].abs_diff(after[3]);
+        if alpha_change != 0 {
+            changed_alpha += 1;
+        }
+        for plane in 0..3 {
+            let colour_change = before[plane].abs_diff(after[plane]);
+            if colour_change != 0 {
+                changed_samples += 1;
+            }
+            let change = colour_change.max(alpha_change);
+            if change != 0 {
+                heat[plane] = (64 + change as u32 * 191 / largest_change as u32) as u8;
+            }
+        }
+    }
+    changed_samples += changed_alpha;
+    let planes = if has_alpha { 4 } else { 3 };
+    Ok(ImageDifference { changed_samples, total_samples: width as u64 * height as u64 * planes, changed_alpha, heatmap })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{Rgb, Rgba, RgbaImage};
+
+    #[test]
+    fn colour_changes_are_counted() {
+        let cover = RgbImage::from_pixel(4, 2, Rgb([10, 20, 30]));
+        let mut stego = cover.clone();
+        stego.put_pixel(0, 0, Rgb([11, 20, 30]));
+        stego.put_pixel(3, 1, Rgb([10, 22, 31]));
+        let difference = image_difference(&DynamicImage::ImageRgb8(cover), &DynamicImage::ImageRgb8(stego)).unwrap();
+        assert_eq!((difference.changed_samples, difference.total_samples, difference.changed_alpha), (3, 24, 0));
+        assert_eq!(difference.heatmap.get_pixel(0, 0), &Rgb([159, 0, 0]));
+        assert_eq!(difference.heatmap.get_pixel(3, 1), &Rgb([0, 255, 159]));
+        assert_eq!(difference.heatmap.get_pixel(1, 0), &Rgb([0, 0, 0]));
+    }
+
+    // Pixels made opaque by embedding are counted, and show in the heatmap,
+    // even where no colour was changed.
+    #[test]
+    fn alpha_changes_are_counted() {
+        let cover = RgbaImage::from_pixel(4, 2, Rgba([10, 20, 30, 128]));
+        let mut stego = cover.clone();
+        stego.put_pixel(0, 0, Rgba([11, 20, 30, 255]));
+        stego.put_pixel(1, 0, Rgba([10, 20, 30, 255]));
+        let difference = image_difference(&DynamicImage::ImageRgba8(cover), &DynamicImage::ImageRgba8(stego)).unwrap();
+        assert_eq!((difference.changed_samples, difference.total_samples, difference.changed_alpha), (3, 32, 2));
+        assert_eq!(difference.heatmap.get_pixel(0, 0), &Rgb([255, 255, 255]));
+        assert_eq!(difference.heatmap.get_pixel(1, 0), &Rgb([255, 255, 255]));
+        assert_eq!(difference.heatmap.get_pixel(2, 0), &Rgb([0, 0, 0]));
+    }
+}
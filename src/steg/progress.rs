@@ -0,0 +1,149 @@
+// Progress reporting and cancellation.
+//
+// Embedding and extracting large files can take a while, so an
+// observer can be told how far through each file the job is,
+// and a cancellation token can stop the job between chunks of data.
+// The token can be cloned and cancelled from another thread.
+
+use crate::steg::StegError;
+
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Observer of the progress of embedding or extracting files.
+// Called with the bytes done so far and the total bytes for each file.
+pub trait ProgressObserver: Send {
+    fn file_progress(&mut self, name: &str, done: u64, total: u64);
+}
+
+// Closures can be used as observers.
+impl<F> ProgressObserver for F
+where
+    F: FnMut(&str, u64, u64) + Send,
+{
+    fn file_progress(&mut self, name: &str, done: u64, total: u64) {
+        self(name, done, total)
+    }
+}
+
+// Token to cancel an embed or extract job.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    // Clear the token so that it can be used for another job.
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+// Progress observer and cancellation token for a job.
+#[derive(Default)]
+pub struct Progress {
+    pub observer: Option<Box<dyn ProgressObserver>>,
+    pub cancel: CancelToken,
+}
+
+impl Progress {
+    pub fn report(&mut self, name: &str, done: u64, total: u64) {
+        if let Some(observer) = &mut self.observer {
+            observer.file_progress(name, done, total);
+        }
+    }
+
+    pub fn check_cancelled(&self) -> Result<(), StegError> {
+        if self.cancel.is_cancelled() {
+            Err(StegError::Cancelled)
+        }
+        else {
+            Ok(())
+        }
+    }
+}
+
+// Reader that reports progress through a file, and stops
+// with an error if the job is cancelled.
+pub struct ProgressReader<'a, R> {
+    inner: R,
+    name: &'a str,
+    done: u64,
+    total: u64,
+    progress: &'a mut Progress,
+}
+
+impl<'a, R: Read> ProgressReader<'a, R> {
+    pub fn new(inner: R, name: &'a str, total: u64, progress: &'a mut Progress) -> Self {
+        ProgressReader { inner, name, done: 0, total, progress }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.progress.cancel.is_cancelled() {
+            return Err(io::Error::other(StegError::Cancelled));
+        }
+        let bytes_read = self.inner.read(buf)?;
+        if bytes_read > 0 {
+            self.done += bytes_read as u64;
+            self.progress.report(self.name, self.done, self.total);
+        }
+        Ok(bytes_read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::steg::embed::PayloadEntry;
+    use crate::steg::options::{Compression, EmbedOptions};
+    use crate::steg::testing::loaded_steg;
+
+    // Embedding cancelled part way through a file stops with an error,
+    // and leaves the image exactly as it was.
+    #[test]
+    fn cancelled_embed_leaves_image_unchanged() {
+        let bytes:Vec<u8> = (0..20_000u32).map(|idx| (idx * 7919).to_le_bytes()[1]).collect();
+        for compression in [Compression::None, Compression::Deflate] {
+            let mut steg = loaded_steg(200, 200);
+            steg.settings.byte_chunk = 1000;
+            let original_image = steg.image.clone();
+
+            let cancel = steg.progress.cancel.clone();
+            steg.progress.observer = Some(Box::new(move |_name: &str, done: u64, total: u64| {
+                if done >= total / 2 {
+                    cancel.cancel();
+                }
+            }));
+            let options = EmbedOptions::builder().compression(compression).build().unwrap();
+            let result = steg.embed(vec![PayloadEntry::from_bytes("a.bin", &bytes)], &options);
+            assert!(matches!(result, Err(StegError::Cancelled)), "{:?}", result);
+            assert_eq!(steg.image, original_image);
+            assert!(!steg.img_changed);
+
+            // Once the token is reset the same image can be embedded into.
+            steg.progress.observer = None;
+            steg.progress.cancel.reset();
+            steg.embed(vec![PayloadEntry::from_bytes("a.bin", &bytes)], &options).unwrap();
+            assert_eq!(steg.extract_to_memory("").unwrap()[0].bytes, bytes);
+        }
+    }
+}
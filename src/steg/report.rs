@@ -0,0 +1,229 @@
+// Image analysis report.
+//
+// Everything that is known about an image from loading it, and from
+// reading its embedded header, gathered into one serializable report
+// so it can be exported as JSON or YAML.
+
+use crate::steg::container::{CONTAINER_HEADER_LEN, DATA_LEN_DIGITS, KDF_PARAMS_LEN, TRAVERSAL_SEED_LEN};
+use crate::steg::extract::EntryMetadata;
+use crate::steg::options::EmbedOptions;
+use crate::steg::{StegError, Steganography};
+
+use serde::Serialize;
+use std::io::{self, Read};
+use std::path::Path;
+
+// Report on an image, and on what is embedded in it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageReport {
+    pub image_file: String,
+    pub width: u32,
+    pub height: u32,
+    pub colour_type: String,
+    pub colour_planes: u8,
+    // Bytes available for embedding with the default options.
+    pub usable_capacity: u64,
+    // Bytes of the embedded header, before the number of files
+    // and the file sections.
+    pub header_overhead: u64,
+    pub coded: bool,
+    // How the data is embedded, if the image is pic coded.
+    pub container: Option<ContainerReport>,
+    // Embedded files, if they can be read.
+    pub entries: Option<Vec<EntryReport>>,
+}
+
+// Details of how the data in a pic coded image is embedded.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContainerReport {
+    pub version: u8,
+    pub password: bool,
+    pub encryption: String,
+    pub compression: String,
+    pub traversal: String,
+    pub fec_copies: u8,
+    pub kdf: Option<KdfReport>,
+}
+
+// Key derivation parameters for a password.
+#[derive(Debug, Clone, Serialize)]
+pub struct KdfReport {
+    pub algorithm: String,
+    pub iterations: u32,
+    pub salt: String,
+}
+
+// An embedded file.
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryReport {
+    pub name: String,
+    pub size: u64,
+}
+
+impl ImageReport {
+    pub fn to_json(&self) -> Result<String, StegError> {
+        serde_json::to_string_pretty(self).map_err(|err| StegError::Io(io::Error::other(err)))
+    }
+
+    pub fn to_yaml(&self) -> Result<String, StegError> {
+        serde_yaml::to_string(self).map_err(|err| StegError::Io(io::Error::other(err)))
+    }
+}
+
+// Method to load an image and report on it.
+// The image is loaded from the path as given, not from the image folder.
+// Embedded files are only listed if there is no password.
+impl Steganography {
+    pub fn analyze(&mut self, path:&str) -> Result<ImageReport, StegError> {
+        self.load_image_file(Path::new(path))?;
+        self.image_report(None)
+    }
+}
+
+// Method to report on the loaded image.
+// Embedded files are listed if there is no password, or if the
+// password given is correct.
+impl Steganography {
+    pub fn image_report(&mut self, pw:Option<&str>) -> Result<ImageReport, StegError> {
+        if self.image.is_none() {
+            return Err(StegError::NoImage);
+        }
+
+        let colour_type = match self.pic_col_planes {
+            4 => "Rgba8",
+            _ => "Rgb8",
+        };
+        let options = EmbedOptions::from_settings(&self.settings);
+
+        let mut report = ImageReport {
+            image_file: self.image_file.clone(),
+            width: self.pic_width,
+            height: self.pic_height,
+            colour_type: colour_type.to_string(),
+            colour_planes: self.pic_col_planes,
+            usable_capacity: self.usable_capacity(&options),
+            header_overhead: self.header_bytes(&options),
+            coded: self.pic_coded,
+            container: None,
+            entries: None,
+        };
+        if !self.pic_coded {
+            return Ok(report);
+        }
+
+        // Read the embedded header, and list the embedded files if possible.
+        let mut entries = Vec::new();
+        let mut sink = |name: &str, metadata: &EntryMetadata, _data: &mut dyn Read| -> io::Result<()> {
+            entries.push(EntryReport { name: name.to_string(), size: metadata.size });
+            Ok(())
+        };
+        let listed = match (self.pic_has_pw, pw) {
+            (true, None) => {
+                // Only read the header, as far as the password.
+                self.set_bit_index(self.settings.prog_code.len() as u64 * 8);
+                self.check_for_password()?;
+                false
+            }
+            (_, pw) => self.extract_to_sink(pw.unwrap_or(""), &mut sink).map(|_| true)?,
+        };
+        if listed {
+            report.entries = Some(entries);
+        }
+
+        let container = &self.container;
+        let code_len = self.settings.prog_code.len() as u64;
+        report.header_overhead = match (container.version, container.has_password) {
+            (1, false) => code_len + 1,
+            (1, true) => code_len + 1 + 32,
+            (_, false) => code_len + CONTAINER_HEADER_LEN + DATA_LEN_DIGITS,
+            (_, true) => code_len + CONTAINER_HEADER_LEN + KDF_PARAMS_LEN + DATA_LEN_DIGITS,
+        };
+        if container.traversal_seed.is_some() {
+            report.header_overhead += TRAVERSAL_SEED_LEN;
+        }
+
+        report.container = Some(ContainerReport {
+            version: container.version,
+            password: container.has_password,
+            encryption: String::from(if container.encrypted { "chacha20-poly1305" } else { "none" }),
+            compression: String::from(if container.compressed { "deflate" } else { "none" }),
+            traversal: String::from(if container.traversal_seed.is_some() { "permuted" } else { "sequential" }),
+            fec_copies: container.fec_copies,
+            kdf: container.kdf.as_ref().map(|kdf| KdfReport {
+                algorithm: String::from("pbkdf2-hmac-sha256"),
+                iterations: kdf.iterations,
+                salt: kdf.salt.iter().map(|byte| format!("{:02x}", byte)).collect(),
+            }),
+        });
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::steg::embed::PayloadEntry;
+    use crate::steg::options::{Compression, Encryption, FecLevel, Traversal};
+    use crate::steg::testing::loaded_steg;
+
+    use serde_json::{json, Value};
+
+    #[test]
+    fn json_report_for_coded_image() {
+        let mut steg = loaded_steg(120, 80);
+        let usable_capacity = steg.usable_capacity(&EmbedOptions::from_settings(&steg.settings));
+        let options = EmbedOptions::builder()
+            .compression(Compression::Deflate)
+            .fec_level(FecLevel::Repeat3)
+            .traversal(Traversal::Permuted)
+            .build()
+            .unwrap();
+        let entries = vec![PayloadEntry::from_bytes("a.txt", b"hello"), PayloadEntry::from_bytes("b.bin", &[0; 300])];
+        steg.embed(entries, &options).unwrap();
+
+        let report:Value = serde_json::from_str(&steg.image_report(None).unwrap().to_json().unwrap()).unwrap();
+        assert_eq!(report["width"], 120);
+        assert_eq!(report["height"], 80);
+        assert_eq!(report["colour_type"], "Rgb8");
+        assert_eq!(report["colour_planes"], 3);
+        assert_eq!(report["usable_capacity"], usable_capacity);
+        assert_eq!(report["header_overhead"], 8 + CONTAINER_HEADER_LEN + TRAVERSAL_SEED_LEN + DATA_LEN_DIGITS);
+        assert_eq!(report["coded"], true);
+        assert_eq!(report["container"], json!({
+            "version": 2,
+            "password": false,
+            "encryption": "none",
+            "compression": "deflate",
+            "traversal": "permuted",
+            "fec_copies": 3,
+            "kdf": null,
+        }));
+        assert_eq!(report["entries"], json!([{ "name": "a.txt", "size": 5 }, { "name": "b.bin", "size": 300 }]));
+    }
+
+    // Files embedded with a password are only listed with the password.
+    #[test]
+    fn json_report_for_password_image() {
+        let mut steg = loaded_steg(120, 80);
+        let options = EmbedOptions::builder()
+            .password("secret")
+            .encryption(Encryption::ChaCha20Poly1305)
+            .kdf_iterations(1000)
+            .build()
+            .unwrap();
+        steg.embed(vec![PayloadEntry::from_bytes("a.txt", b"hello")], &options).unwrap();
+
+        let report:Value = serde_json::from_str(&steg.image_report(None).unwrap().to_json().unwrap()).unwrap();
+        assert_eq!(report["header_overhead"], 8 + CONTAINER_HEADER_LEN + KDF_PARAMS_LEN + DATA_LEN_DIGITS);
+        assert_eq!(report["container"]["password"], true);
+        assert_eq!(report["container"]["encryption"], "chacha20-poly1305");
+        assert_eq!(report["container"]["traversal"], "sequential");
+        assert_eq!(report["container"]["kdf"]["algorithm"], "pbkdf2-hmac-sha256");
+        assert_eq!(report["container"]["kdf"]["iterations"], 1000);
+        assert_eq!(report["container"]["kdf"]["salt"].as_str().unwrap().len(), 32);
+        assert_eq!(report["entries"], Value::Null);
+
+        let report:Value = serde_json::from_str(&steg.image_report(Some("secret")).unwrap().to_json().unwrap()).unwrap();
+        assert_eq!(report["entries"], json!([{ "name": "a.txt", "size": 5 }]));
+    }
+}
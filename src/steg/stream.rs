@@ -0,0 +1,78 @@
+// Hidden data stream methods.
+//
+// Streams allow the hidden data area of an image to be treated
+// like a file, so that data can be copied into or out of an image
+// with the standard io traits.
+// Stream positions are in bytes from the start of the hidden data,
+// which is the first bit of the image.
+
+use crate::steg::Steganography;
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+// Reader over the hidden data in an image.
+// Reading starts from the current cursor position,
+// and ends at the end of the image.
+pub struct StegReader<'a> {
+    steg: &'a mut Steganography,
+}
+
+// Writer into the hidden data in an image.
+// Writing starts from the current cursor position,
+// and fails once the end of the image is reached.
+pub struct StegWriter<'a> {
+    steg: &'a mut Steganography,
+}
+
+// Methods to create streams over the hidden data.
+impl Steganography {
+    pub fn reader(&mut self) -> StegReader<'_> {
+        StegReader { steg: self }
+    }
+
+    pub fn writer(&mut self) -> StegWriter<'_> {
+        StegWriter { steg: self }
+    }
+}
+
+impl Read for StegReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(self.steg.read_data_into(buf))
+    }
+}
+
+impl Seek for StegReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        // Work out the new position in bytes.
+        let capacity = self.steg.bit_capacity() / 8;
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => (self.steg.bit_index() / 8).checked_add_signed(offset),
+            SeekFrom::End(offset) => capacity.checked_add_signed(offset),
+        };
+
+        // Positions before the start of the data are invalid.
+        // Positions past the end are moved to the end.
+        match new_pos {
+            Some(new_pos) => {
+                let new_pos = new_pos.min(capacity);
+                self.steg.set_bit_index(new_pos * 8);
+                Ok(new_pos)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+impl Write for StegWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(self.steg.write_data_to_image(buf) as usize)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
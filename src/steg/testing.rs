@@ -0,0 +1,40 @@
+// Helpers shared by the steganography tests.
+
+use crate::settings::Settings;
+use crate::steg::options::EmbedOptions;
+use crate::steg::Steganography;
+
+use image::{DynamicImage, RgbImage};
+use std::fs;
+use std::path::PathBuf;
+
+// Steganography with a black rgb image loaded, ready to embed into,
+// as if the image had been loaded from a file.
+pub fn loaded_steg(width:u32, height:u32) -> Steganography {
+    let mut steg = Steganography::new(Settings::default());
+    (steg.pic_width, steg.pic_height) = (width, height);
+    steg.pic_col_planes = 3;
+    steg.image = Some(DynamicImage::ImageRgb8(RgbImage::new(width, height)));
+    steg.img_to_proc = true;
+    steg.embed_capacity = steg.usable_capacity(&EmbedOptions::from_settings(&steg.settings));
+    steg
+}
+
+// Repeatable bytes that look random enough.
+pub fn noise(len:usize, seed:u32) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(2_654_435_761).wrapping_add(1);
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+// Empty folder for a test to write files to, unique to the test process.
+pub fn test_folder(name:&str) -> PathBuf {
+    let folder = std::env::temp_dir().join(format!("hidey-test-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&folder);
+    fs::create_dir_all(&folder).unwrap();
+    folder
+}
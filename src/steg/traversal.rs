@@ -0,0 +1,124 @@
+// Permuted traversal of the embedded data.
+//
+// Normally the embedded data fills the image from the top, a colour
+// plane at a time, so all the changed pixels are at the top of the
+// image. With the permuted traversal the bits of the container data
+// are scattered over the image instead, in an order that comes from
+// a seed recorded in the container header.
+// The order is a permutation of the bits from the start of the data
+// to the end of the lowest bit levels that the data needs, so the
+// data uses no higher bits than it would have otherwise.
+// Each bit position is computed as it is read or written, with a
+// small Feistel network, so no table of positions is kept.
+
+// Bytes of the seed in the container header.
+pub const SEED_LEN: usize = 8;
+
+// Rounds of the Feistel network.
+const ROUNDS: usize = 4;
+
+// Permutation of the bit positions of the embedded data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permutation {
+    // Bit index of the first permuted bit.
+    start: u64,
+    // Number of bits that are permuted.
+    len: u64,
+    // Bits in each half of the Feistel network input.
+    half_bits: u32,
+    round_keys: [u64; ROUNDS],
+}
+
+impl Permutation {
+    // Permutation of the bits from the start of the data, for the given
+    // number of data bits, up to the end of the last bit level used.
+    // A bit level is one bit of every rgb colour component,
+    // and the permutation never goes past the capacity of the image.
+    pub fn new(seed:u64, start:u64, data_bits:u64, level_bits:u64, capacity:u64) -> Self {
+        let end = if level_bits == 0 {
+            start
+        }
+        else {
+            start.saturating_add(data_bits).div_ceil(level_bits).saturating_mul(level_bits)
+        };
+        let len = end.min(capacity).saturating_sub(start);
+
+        // Smallest even number of bits that covers all the positions.
+        let domain_bits = u64::BITS - len.saturating_sub(1).leading_zeros();
+        let half_bits = domain_bits.div_ceil(2);
+
+        let mut state = seed;
+        let round_keys = [(); ROUNDS].map(|_| {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            mix(state)
+        });
+        Permutation { start, len, half_bits, round_keys }
+    }
+
+    // Bit index that a bit of the data is actually stored at.
+    // Bits outside the permuted range are stored where they are.
+    pub fn map(&self, bit_index:u64) -> u64 {
+        if bit_index < self.start || bit_index - self.start >= self.len {
+            return bit_index;
+        }
+        // Cycle walk, so that positions past the end of the range,
+        // that the network can produce, are never used.
+        let mut position = bit_index - self.start;
+        loop {
+            position = self.feistel(position);
+            if position < self.len {
+                return self.start + position;
+            }
+        }
+    }
+
+    // One pass of the Feistel network, a permutation of the
+    // numbers with twice half_bits bits.
+    fn feistel(&self, position:u64) -> u64 {
+        let mask = (1u64 << self.half_bits) - 1;
+        let mut left = position >> self.half_bits;
+        let mut right = position & mask;
+        for round_key in self.round_keys {
+            (left, right) = (right, left ^ (mix(right ^ round_key) & mask));
+        }
+        (left << self.half_bits) | right
+    }
+}
+
+// Mix the bits of a number (the splitmix64 finaliser).
+fn mix(value:u64) -> u64 {
+    let mut value = value;
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every bit of the range is used exactly once, and bits outside
+    // the range stay where they are.
+    #[test]
+    fn permutation_is_a_bijection() {
+        for (start, data_bits, level_bits) in [(0, 1, 1), (100, 5, 30), (77, 1000, 333), (5, 4096, 4096)] {
+            let capacity = level_bits * 8;
+            let permutation = Permutation::new(42, start, data_bits, level_bits, capacity);
+            let end = (start + data_bits).div_ceil(level_bits) * level_bits;
+            let mut mapped:Vec<u64> = (start..end).map(|bit_index| permutation.map(bit_index)).collect();
+            mapped.sort_unstable();
+            assert!(mapped.iter().copied().eq(start..end), "start: {}, data bits: {}", start, data_bits);
+            if start > 0 {
+                assert_eq!(permutation.map(start - 1), start - 1);
+            }
+            assert_eq!(permutation.map(end), end);
+        }
+    }
+
+    #[test]
+    fn seed_changes_the_order() {
+        let first = Permutation::new(1, 0, 1000, 1000, 8000);
+        let second = Permutation::new(2, 0, 1000, 1000, 8000);
+        assert!((0..1000).any(|bit_index| first.map(bit_index) != second.map(bit_index)));
+    }
+}
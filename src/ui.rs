@@ -1,46 +1,140 @@
 // Steganography UI configuration and interfaces
 // to Steganography class methods.
 
-use log::{info};
+mod dialogs;
+mod drop;
+mod embed;
+mod extract;
+mod image_view;
+mod info_panel;
+mod open;
+mod preview;
+mod save;
+mod state;
+mod worker;
+
+use log::{info, warn};
 use std::cell::RefCell;
+use std::path::Path;
 use std::rc::Rc;
 
 extern crate gtk;
-use gtk::{gio, prelude::*};
+use gtk::{gio, glib, prelude::*};
 use gtk::{Application, ApplicationWindow};
 
-use crate::SETTINGS;
-use crate::settings::Settings;
-use crate::steg::Steganography;
+use hidey::steg::progress::CancelToken;
+use hidey::steg::Steganography;
+
+use crate::ui::dialogs::show_info;
+use crate::ui::drop::connect_drop;
+use crate::ui::embed::embed_into_image;
+use crate::ui::extract::extract_from_image;
+use crate::ui::image_view::ImageView;
+use crate::ui::info_panel::{container_text, file_count_text, yes_no, InfoPanel};
+use crate::ui::open::{open_image, open_recent_image, refresh_recent_menu};
+use crate::ui::preview::preview_image;
+use crate::ui::save::{confirm_close, save_image, save_image_as};
+use crate::ui::state::GuiState;
+
+// Main window widgets that menu actions update.
+// Widgets are reference counted, so clones refer to the same window.
+#[derive(Clone)]
+pub struct MainWindow {
+    pub window: ApplicationWindow,
+    pub image_view: ImageView,
+    pub info_panel: InfoPanel,
+    pub status: gtk::Label,
+    // Progress of a running job, with a button to cancel it.
+    pub progress_box: gtk::Box,
+    pub progress_bar: gtk::ProgressBar,
+    pub cancel: Rc<RefCell<Option<CancelToken>>>,
+    // Recently opened images, for the File menu.
+    pub recent_menu: gio::Menu,
+    // State saved between runs.
+    pub state: Rc<RefCell<GuiState>>,
+    // Native dialogs aren't kept alive by their parent window,
+    // so the open one is held here until it is done with.
+    pub native_dialog: Rc<RefCell<Option<gtk::NativeDialog>>>,
+}
 
 // Function to create application UI elements.
 pub fn on_startup(app: &gtk::Application, img_steg: Rc<RefCell<Steganography>>) {
+    // Create the main window, for menu items to update.
+    let main_window = build_main_window(app, &img_steg.borrow());
+    connect_drop(&main_window, &img_steg);
+
     // Create menubar.
     let menubar = gio::Menu::new();
 
-    // Clone _img_steg to pass into menu item closures.
+    // Clone img_steg and the main window to pass into menu item closures.
     let img_steg_clone = img_steg.clone();
+    let main_window_clone = main_window.clone();
 
     // Create an action for an 'Open' menu item.
     // Include reference to steg instance so that menu item
     // can trigger methods.
     let open = gio::ActionEntry::builder("open")
-        .activate(move |_, _, _| open_image(&img_steg_clone))
+        .activate(move |_, _, _| open_image(&main_window_clone, &img_steg_clone))
+        .build();
+
+    // Create an action for the 'Recent' menu items,
+    // with the image file to open as the parameter.
+    let img_steg_clone = img_steg.clone();
+    let main_window_clone = main_window.clone();
+    let open_recent = gio::ActionEntry::builder("open_recent")
+        .parameter_type(Some(&String::static_variant_type()))
+        .activate(move |_, _, parameter| {
+            if let Some(image_file) = parameter.and_then(|parameter| parameter.get::<String>()) {
+                open_recent_image(&main_window_clone, &img_steg_clone, &image_file);
+            }
+        })
         .build();
 
     // Create an action for a 'Save' menu item.
+    let img_steg_clone = img_steg.clone();
+    let main_window_clone = main_window.clone();
     let save = gio::ActionEntry::builder("save")
-        .activate(|_, _, _| save_image())
+        .activate(move |_, _, _| save_image(&main_window_clone, &img_steg_clone, || ()))
+        .build();
+
+    // Create an action for a 'Save As' menu item.
+    let img_steg_clone = img_steg.clone();
+    let main_window_clone = main_window.clone();
+    let save_as = gio::ActionEntry::builder("save_as")
+        .activate(move |_, _, _| save_image_as(&main_window_clone, &img_steg_clone, || ()))
         .build();
 
     // Create an action for an 'Embed' menu item.
+    let img_steg_clone = img_steg.clone();
+    let main_window_clone = main_window.clone();
     let embed = gio::ActionEntry::builder("embed")
-        .activate(|_, _, _| embed_into_image())
+        .activate(move |_, _, _| embed_into_image(&main_window_clone, &img_steg_clone))
+        .build();
+
+    // Create an action for an 'Extract' menu item.
+    let img_steg_clone = img_steg.clone();
+    let main_window_clone = main_window.clone();
+    let extract = gio::ActionEntry::builder("extract")
+        .activate(move |_, _, _| extract_from_image(&main_window_clone, &img_steg_clone))
         .build();
 
     // Create an action for a 'Preview' menu item.
+    let img_steg_clone = img_steg.clone();
+    let main_window_clone = main_window.clone();
     let preview = gio::ActionEntry::builder("preview")
-        .activate(|_, _, _| preview_image())
+        .activate(move |_, _, _| preview_image(&main_window_clone, &img_steg_clone))
+        .build();
+
+    // Create an action for an 'Info Panel' menu item,
+    // that shows or hides the information panel.
+    let main_window_clone = main_window.clone();
+    let info_panel = gio::ActionEntry::builder("info_panel")
+        .state(true.to_variant())
+        .activate(move |_, action, _| {
+            let shown = !action.state().and_then(|state| state.get::<bool>()).unwrap_or(true);
+            action.set_state(&shown.to_variant());
+            main_window_clone.info_panel.revealer.set_reveal_child(shown);
+        })
         .build();
 
     // Create an action for an 'About' menu item.
@@ -54,35 +148,50 @@ pub fn on_startup(app: &gtk::Application, img_steg: Rc<RefCell<Steganography>>)
         .build();
 
     // Create an action for an applicatioin quit menu item.
+    // Closing the window quits, once any unsaved embedding is dealt with.
+    let main_window_clone = main_window.clone();
     let quit = gio::ActionEntry::builder("quit")
-        .activate(|app: &gtk::Application, _, _| app.quit())
+        .activate(move |_, _, _| main_window_clone.window.close())
         .build();
 
     // Add menu item actions to the application UI. 
-    app.add_action_entries([open, save, embed, preview, about, help, quit]);
+    app.add_action_entries([open, open_recent, save, save_as, embed, extract, preview, info_panel, about, help, quit]);
 
     // Create menubar full of menu options.
     let menubar = {
         let file_menu = {
             let open_menu_item = gio::MenuItem::new(Some("Open"), Some("app.open"));
             let save_menu_item = gio::MenuItem::new(Some("Save"), Some("app.save"));
+            let save_as_menu_item = gio::MenuItem::new(Some("Save As"), Some("app.save_as"));
 
             let file_menu = gio::Menu::new();
             file_menu.append_item(&open_menu_item);
+            file_menu.append_submenu(Some("Recent"), &main_window.recent_menu);
             file_menu.append_item(&save_menu_item);
+            file_menu.append_item(&save_as_menu_item);
             file_menu
         };
 
         let edit_menu = {
             let embed_menu_item = gio::MenuItem::new(Some("Embed"), Some("app.embed"));
+            let extract_menu_item = gio::MenuItem::new(Some("Extract"), Some("app.extract"));
             let preview_menu_item = gio::MenuItem::new(Some("Preview"), Some("app.preview"));
 
             let edit_menu = gio::Menu::new();
             edit_menu.append_item(&embed_menu_item);
+            edit_menu.append_item(&extract_menu_item);
             edit_menu.append_item(&preview_menu_item);
             edit_menu
         };
 
+        let view_menu = {
+            let info_panel_menu_item = gio::MenuItem::new(Some("Info Panel"), Some("app.info_panel"));
+
+            let view_menu = gio::Menu::new();
+            view_menu.append_item(&info_panel_menu_item);
+            view_menu
+        };
+
         let help_menu = {
             let about_menu_item = gio::MenuItem::new(Some("About"), Some("app.about"));
             let help_menu_item = gio::MenuItem::new(Some("Help"), Some("app.help"));
@@ -104,6 +213,7 @@ pub fn on_startup(app: &gtk::Application, img_steg: Rc<RefCell<Steganography>>)
         // Create an application menubar and associate items to it.
         menubar.append_submenu(Some("File"), &file_menu);
         menubar.append_submenu(Some("Edit"), &edit_menu);
+        menubar.append_submenu(Some("View"), &view_menu);
         menubar.append_submenu(Some("Help"), &help_menu);
         menubar.append_submenu(Some("Quit"), &quit_menu);
 
@@ -112,52 +222,165 @@ pub fn on_startup(app: &gtk::Application, img_steg: Rc<RefCell<Steganography>>)
     };
     // Associate menubar with the application UI.
     app.set_menubar(Some(&menubar));
+
+    // Ask before closing with embedded files that haven't been saved.
+    let main_window_clone = main_window.clone();
+    main_window.window.connect_close_request(move |_| {
+        // The image is with the worker while a job is running.
+        if img_steg.borrow().img_proc_running {
+            show_info(&main_window_clone.window, "Job still running", "Wait for the job to finish, or cancel it, before closing.");
+            return glib::Propagation::Stop;
+        }
+        if !img_steg.borrow().img_changed {
+            save_state(&main_window_clone);
+            return glib::Propagation::Proceed;
+        }
+        confirm_close(&main_window_clone, &img_steg);
+        glib::Propagation::Stop
+    });
 }
 
 // Create the application window and add any children.
-pub fn on_activate(application: &Application) {
-    // Access lazy global settings from main.
-    let settings_lock = SETTINGS.lock().unwrap();
-    let settings: &Settings = &*settings_lock;
+fn build_main_window(application: &Application, img_steg: &Steganography) -> MainWindow {
+    // Image display, filling the window, with the information panel beside it.
+    let image_view = ImageView::new();
+    let info_panel = InfoPanel::new();
+    let image_row = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+    image_row.append(&image_view.widget);
+    image_row.append(&info_panel.revealer);
+
+    // Status line for the loaded image.
+    let status = gtk::Label::builder()
+        .halign(gtk::Align::Start)
+        .margin_start(6)
+        .margin_end(6)
+        .margin_top(3)
+        .margin_bottom(3)
+        .build();
 
+    // Progress of a running job, hidden until a job starts.
+    let progress_bar = gtk::ProgressBar::builder()
+        .show_text(true)
+        .hexpand(true)
+        .valign(gtk::Align::Center)
+        .build();
+    let cancel_button = gtk::Button::with_mnemonic("_Cancel");
+    let progress_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(6)
+        .margin_start(6)
+        .margin_end(6)
+        .margin_top(3)
+        .visible(false)
+        .build();
+    progress_box.append(&progress_bar);
+    progress_box.append(&cancel_button);
+    let cancel:Rc<RefCell<Option<CancelToken>>> = Rc::new(RefCell::new(None));
+    let cancel_clone = cancel.clone();
+    cancel_button.connect_clicked(move |_| {
+        if let Some(token) = cancel_clone.borrow().as_ref() {
+            info!("Cancelling job.");
+            token.cancel();
+        }
+    });
+
+    let content = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    content.append(&image_row);
+    content.append(&progress_box);
+    content.append(&status);
+
+    // Window size from the last run, or else from the settings.
+    let state = GuiState::load();
     let window = ApplicationWindow::builder()
         .application(application)
         .title("Hidey-Ho")
-        .default_width(settings.window_width)
-        .default_height(settings.window_height)
+        .default_width(state.window_width.unwrap_or(img_steg.settings.window_width))
+        .default_height(state.window_height.unwrap_or(img_steg.settings.window_height))
+        .maximized(state.window_maximized)
         .show_menubar(true)
+        .child(&content)
         .build();
 
-    // Present the UI and all elements.
-    window.present();
+    let main_window = MainWindow {
+        window,
+        image_view,
+        info_panel,
+        status,
+        progress_box,
+        progress_bar,
+        cancel,
+        recent_menu: gio::Menu::new(),
+        state: Rc::new(RefCell::new(state)),
+        native_dialog: Rc::new(RefCell::new(None)),
+    };
+    refresh_recent_menu(&main_window);
+    refresh_image(&main_window, img_steg);
+    main_window
 }
 
-pub fn open_image(_img_steg: &Rc<RefCell<Steganography>>) {
-    info!("Open image menu item selected.");
-
-    // Borrow the inner value mutably and obtain a mutable reference.
-    // This will allow access to the fields and methods of Steganography.
-    let mut img_steg_refmut = _img_steg.borrow_mut();
-    let img_steg = &mut *img_steg_refmut;
+// Save the window size, along with the rest of the state, for next time.
+fn save_state(main_window: &MainWindow) {
+    let mut state = main_window.state.borrow_mut();
+    let (width, height) = main_window.window.default_size();
+    state.window_width = Some(width);
+    state.window_height = Some(height);
+    state.window_maximized = main_window.window.is_maximized();
+    if let Err(err) = state.save() {
+        warn!("Unable to save state: {}", err);
+    }
+}
 
-    // <TODO> Remove these tests of access to Steganography variables and methods.
-    info!("Steg test - read image to open variable: {}", img_steg.img_to_proc);
-    img_steg.img_to_proc = true;
-    info!("Steg test - set image to open variable: {}", img_steg.img_to_proc);
-    // Call initialise parameters method.
-    img_steg.init_image_params();
+// Present the application window.
+pub fn on_activate(application: &Application) {
+    if let Some(window) = application.windows().first() {
+        window.present();
+    }
 }
 
-pub fn save_image() {
-    info!("Save image menu item selected.");
+// Update the window to show a newly loaded or embedded image, and its status.
+// Rebuilding the image view is only worth doing when the image has changed.
+pub fn refresh_image(main_window: &MainWindow, img_steg: &Steganography) {
+    main_window.image_view.set_image(img_steg.image.as_ref());
+    refresh_window(main_window, img_steg);
 }
 
-pub fn embed_into_image() {
-    info!("Embed into image menu item selected.");
+// Update the window to show the status of the loaded image.
+// The image itself is left as it is shown.
+pub fn refresh_window(main_window: &MainWindow, img_steg: &Steganography) {
+    if img_steg.image.is_some() {
+        let file_name = Path::new(&img_steg.image_file).file_name().unwrap_or_default().to_string_lossy();
+        // Unsaved embedding is marked in the title.
+        let changed = if img_steg.img_changed { " *" } else { "" };
+        main_window.window.set_title(Some(&format!("Hidey-Ho - {}{}", file_name, changed)));
+    } else {
+        main_window.window.set_title(Some("Hidey-Ho"));
+    }
+    main_window.info_panel.update(img_steg);
+    main_window.status.set_text(&status_text(img_steg));
 }
 
-pub fn preview_image() {
-    info!("Preview image menu item selected.");
+// One line summary of the loaded image, for the status bar.
+pub fn status_text(img_steg: &Steganography) -> String {
+    if !img_steg.img_to_proc {
+        return String::from("No image loaded");
+    }
+    let mut text = format!(
+        "{} x {}  |  {} colour planes  |  capacity: {} bytes  |  coded: {}",
+        img_steg.pic_width,
+        img_steg.pic_height,
+        img_steg.pic_col_planes,
+        img_steg.embed_capacity,
+        yes_no(img_steg.pic_coded)
+    );
+    if img_steg.pic_coded {
+        text.push_str(&format!(
+            "  |  password: {}  |  container: {}  |  files: {}",
+            yes_no(img_steg.pic_has_pw),
+            container_text(img_steg),
+            file_count_text(img_steg)
+        ));
+    }
+    text
 }
 
 pub fn about_app() {
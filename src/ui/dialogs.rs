@@ -0,0 +1,48 @@
+// Common dialogs.
+
+use gtk::prelude::*;
+
+// Show an error message, with more detail below it.
+pub fn show_error(parent: &impl IsA<gtk::Window>, message: &str, detail: &str) {
+    show_message(parent, gtk::MessageType::Error, message, detail);
+}
+
+// Show an information message, with more detail below it.
+pub fn show_info(parent: &impl IsA<gtk::Window>, message: &str, detail: &str) {
+    show_message(parent, gtk::MessageType::Info, message, detail);
+}
+
+fn show_message(parent: &impl IsA<gtk::Window>, message_type: gtk::MessageType, message: &str, detail: &str) {
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(parent)
+        .modal(true)
+        .message_type(message_type)
+        .buttons(gtk::ButtonsType::Close)
+        .text(message)
+        .secondary_text(detail)
+        .build();
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.present();
+}
+
+// Ask the user to confirm an action, and do it if they do.
+pub fn confirm(parent: &impl IsA<gtk::Window>, message: &str, detail: &str, accept_label: &str, on_accept: impl Fn() + 'static) {
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(parent)
+        .modal(true)
+        .message_type(gtk::MessageType::Question)
+        .buttons(gtk::ButtonsType::None)
+        .text(message)
+        .secondary_text(detail)
+        .build();
+    dialog.add_button("_Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button(accept_label, gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Cancel);
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+        if response == gtk::ResponseType::Accept {
+            on_accept();
+        }
+    });
+    dialog.present();
+}
@@ -0,0 +1,89 @@
+// Drag and drop onto the main window.
+//
+// Dropping a single lossless image opens it. Dropping other files, or
+// several files, once an image is open, opens the embed dialog with
+// the files added to embed.
+
+use log::{info, warn};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use gtk::{gdk, prelude::*};
+
+use hidey::steg::options::OutputFormat;
+use hidey::steg::Steganography;
+
+use crate::ui::dialogs::show_error;
+use crate::ui::embed::embed_paths;
+use crate::ui::open::load_image;
+use crate::ui::MainWindow;
+
+// Accept files dropped onto the image.
+pub fn connect_drop(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>) {
+    let drop_target = gtk::DropTarget::new(gdk::FileList::static_type(), gdk::DragAction::COPY);
+    let main_window_clone = main_window.clone();
+    let img_steg = img_steg.clone();
+    drop_target.connect_drop(move |_, value, _, _| match value.get::<gdk::FileList>() {
+        Ok(file_list) => drop_files(&main_window_clone, &img_steg, &dropped_paths(&file_list)),
+        Err(err) => {
+            warn!("Unable to read dropped files: {}", err);
+            false
+        }
+    });
+    main_window.image_view.widget.add_controller(drop_target);
+}
+
+// Local paths of dropped files.
+pub fn dropped_paths(file_list: &gdk::FileList) -> Vec<PathBuf> {
+    file_list.files().iter().filter_map(|file| file.path()).collect()
+}
+
+// Open a dropped image, or embed dropped files.
+// Returns whether the drop was accepted.
+fn drop_files(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>, paths: &[PathBuf]) -> bool {
+    info!("Files dropped: {}", paths.len());
+    let (img_proc_running, img_to_proc) = {
+        let img_steg = img_steg.borrow();
+        (img_steg.img_proc_running, img_steg.img_to_proc)
+    };
+    if img_proc_running {
+        show_error(&main_window.window, "Unable to use dropped files", "Wait for the job to finish first.");
+        return false;
+    }
+
+    match paths {
+        [] => {
+            show_error(&main_window.window, "Unable to use dropped files", "Only local files can be dropped.");
+            false
+        }
+        [path] if is_image(path) => {
+            load_image(main_window, img_steg, path);
+            true
+        }
+        _ if img_to_proc => {
+            embed_paths(main_window, img_steg, paths);
+            true
+        }
+        _ => {
+            let names:Vec<String> = paths
+                .iter()
+                .map(|path| path.file_name().unwrap_or_default().to_string_lossy().into_owned())
+                .collect();
+            show_error(
+                &main_window.window,
+                "Unsupported image type",
+                &format!(
+                    "Drop a single png, bmp or tiff image to open it, then drop files onto it to embed them.\n\nDropped: {}",
+                    names.join(", ")
+                ),
+            );
+            false
+        }
+    }
+}
+
+// Lossless image file, that can be opened.
+fn is_image(path: &Path) -> bool {
+    path.is_file() && OutputFormat::from_path(&path.to_string_lossy()).is_ok()
+}
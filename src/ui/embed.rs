@@ -0,0 +1,436 @@
+// Embed dialog.
+//
+// Files to embed are picked one or more at a time, or a folder at a
+// time, and checked against the embedding capacity of the image as
+// they are added, so that problems show up before the image is changed.
+
+use log::{error, info};
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use gtk::{gdk, gio, prelude::*};
+use zeroize::Zeroizing;
+
+use hidey::password::Password;
+use hidey::steg::capacity::entry_bytes;
+use hidey::steg::embed::{PayloadEntry, MAX_FILES};
+use hidey::steg::options::{Compression, EmbedOptions, EmbedOptionsBuilder};
+use hidey::steg::{StegError, Steganography};
+
+use crate::ui::dialogs::show_error;
+use crate::ui::drop::dropped_paths;
+use crate::ui::worker::run_job;
+use crate::ui::{refresh_image, refresh_window, MainWindow};
+
+// File picked to embed.
+struct PayloadFile {
+    path: PathBuf,
+    name: String,
+    len: u64,
+}
+
+// Embed dialog widgets, and the files picked so far.
+// Widgets are reference counted, so clones refer to the same dialog.
+#[derive(Clone)]
+struct EmbedDialog {
+    dialog: gtk::Dialog,
+    file_list: gtk::ListBox,
+    meter: gtk::ProgressBar,
+    use_password: gtk::CheckButton,
+    password: gtk::PasswordEntry,
+    confirm: gtk::PasswordEntry,
+    error_label: gtk::Label,
+    files: Rc<RefCell<Vec<PayloadFile>>>,
+    main_window: MainWindow,
+    img_steg: Rc<RefCell<Steganography>>,
+}
+
+pub fn embed_into_image(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>) {
+    info!("Embed menu item selected.");
+    embed_paths(main_window, img_steg, &[]);
+}
+
+// Open the embed dialog, with files or folders already added.
+pub fn embed_paths(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>, paths: &[PathBuf]) {
+    if !img_steg.borrow().img_to_proc {
+        show_error(&main_window.window, "Unable to embed files", "Open an image to embed the files into first.");
+        return;
+    }
+    let embed_dialog = EmbedDialog::new(main_window, img_steg);
+    embed_dialog.add_paths(paths);
+    embed_dialog.dialog.present();
+}
+
+impl EmbedDialog {
+    fn new(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>) -> Self {
+        let dialog = gtk::Dialog::builder()
+            .title("Embed Files")
+            .transient_for(&main_window.window)
+            .modal(true)
+            .default_width(520)
+            .default_height(460)
+            .build();
+        dialog.add_button("_Cancel", gtk::ResponseType::Cancel);
+        dialog.add_button("_Embed", gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+
+        // List of files to embed, each with its size and a remove button.
+        let file_list = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .build();
+        file_list.set_placeholder(Some(&gtk::Label::new(Some("No files added"))));
+        let file_scroll = gtk::ScrolledWindow::builder()
+            .child(&file_list)
+            .min_content_height(160)
+            .vexpand(true)
+            .build();
+
+        let add_files_button = gtk::Button::with_mnemonic("Add _Files…");
+        let add_folder_button = gtk::Button::with_mnemonic("Add F_older…");
+        let clear_button = gtk::Button::with_mnemonic("C_lear");
+        let button_row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        button_row.append(&add_files_button);
+        button_row.append(&add_folder_button);
+        button_row.append(&clear_button);
+
+        // Bytes needed, header and file overhead included, against the capacity.
+        let meter = gtk::ProgressBar::builder()
+            .show_text(true)
+            .build();
+
+        let use_password = gtk::CheckButton::with_mnemonic("Protect with a _password");
+        let password = gtk::PasswordEntry::builder()
+            .show_peek_icon(true)
+            .hexpand(true)
+            .sensitive(false)
+            .build();
+        let confirm = gtk::PasswordEntry::builder()
+            .show_peek_icon(true)
+            .hexpand(true)
+            .sensitive(false)
+            .build();
+        let password_grid = gtk::Grid::builder()
+            .row_spacing(6)
+            .column_spacing(6)
+            .build();
+        password_grid.attach(&gtk::Label::builder().label("Password").halign(gtk::Align::Start).build(), 0, 0, 1, 1);
+        password_grid.attach(&password, 1, 0, 1, 1);
+        password_grid.attach(&gtk::Label::builder().label("Confirm").halign(gtk::Align::Start).build(), 0, 1, 1, 1);
+        password_grid.attach(&confirm, 1, 1, 1, 1);
+
+        let error_label = gtk::Label::builder()
+            .halign(gtk::Align::Start)
+            .wrap(true)
+            .css_classes(["error"])
+            .build();
+
+        let content = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(6)
+            .margin_start(12)
+            .margin_end(12)
+            .margin_top(12)
+            .margin_bottom(12)
+            .build();
+        content.append(&gtk::Label::builder().label("Files to embed").halign(gtk::Align::Start).build());
+        content.append(&file_scroll);
+        content.append(&button_row);
+        content.append(&meter);
+        content.append(&use_password);
+        content.append(&password_grid);
+        content.append(&error_label);
+        dialog.content_area().append(&content);
+
+        // Files and folders can also be dropped onto the dialog.
+        let drop_target = gtk::DropTarget::new(gdk::FileList::static_type(), gdk::DragAction::COPY);
+        content.add_controller(drop_target.clone());
+
+        let embed_dialog = EmbedDialog {
+            dialog,
+            file_list,
+            meter,
+            use_password,
+            password,
+            confirm,
+            error_label,
+            files: Rc::new(RefCell::new(Vec::new())),
+            main_window: main_window.clone(),
+            img_steg: img_steg.clone(),
+        };
+
+        let this = embed_dialog.clone();
+        add_files_button.connect_clicked(move |_| this.choose_payload(gtk::FileChooserAction::Open));
+        let this = embed_dialog.clone();
+        add_folder_button.connect_clicked(move |_| this.choose_payload(gtk::FileChooserAction::SelectFolder));
+        let this = embed_dialog.clone();
+        clear_button.connect_clicked(move |_| {
+            this.files.borrow_mut().clear();
+            this.update();
+        });
+        let this = embed_dialog.clone();
+        embed_dialog.use_password.connect_toggled(move |use_password| {
+            this.password.set_sensitive(use_password.is_active());
+            this.confirm.set_sensitive(use_password.is_active());
+            this.update();
+        });
+        let this = embed_dialog.clone();
+        embed_dialog.password.connect_changed(move |_| this.update());
+        let this = embed_dialog.clone();
+        embed_dialog.confirm.connect_changed(move |_| this.update());
+
+        let this = embed_dialog.clone();
+        drop_target.connect_drop(move |_, value, _, _| match value.get::<gdk::FileList>() {
+            Ok(file_list) => {
+                this.add_paths(&dropped_paths(&file_list));
+                true
+            }
+            Err(_) => false,
+        });
+
+        let this = embed_dialog.clone();
+        embed_dialog.dialog.connect_response(move |dialog, response| {
+            if response != gtk::ResponseType::Accept {
+                dialog.close();
+                return;
+            }
+            this.embed();
+        });
+        embed_dialog
+    }
+
+    // Pick files, or a folder, to add to the files to embed.
+    fn choose_payload(&self, action:gtk::FileChooserAction) {
+        let (title, accept) = match action {
+            gtk::FileChooserAction::SelectFolder => ("Add Folder", "_Add Folder"),
+            _ => ("Add Files", "_Add"),
+        };
+        let chooser = gtk::FileChooserNative::new(Some(title), Some(&self.dialog), action, Some(accept), Some("_Cancel"));
+        chooser.set_modal(true);
+        chooser.set_select_multiple(action == gtk::FileChooserAction::Open);
+
+        // Start in the folder files were last picked from.
+        let embed_folder = self.main_window.state.borrow().embed_folder.clone();
+        if let Some(embed_folder) = embed_folder.filter(|folder| Path::new(folder).is_dir()) {
+            let _ = chooser.set_current_folder(Some(&gio::File::for_path(&embed_folder)));
+        }
+
+        let this = self.clone();
+        chooser.connect_response(move |chooser, response| {
+            if response == gtk::ResponseType::Accept {
+                let chosen = chooser.files();
+                let paths:Vec<PathBuf> = (0..chosen.n_items())
+                    .filter_map(|index| chosen.item(index).and_downcast::<gio::File>())
+                    .filter_map(|file| file.path())
+                    .collect();
+                if let Some(folder) = paths.first().and_then(|path| path.parent()) {
+                    this.main_window.state.borrow_mut().embed_folder = Some(folder.to_string_lossy().into_owned());
+                }
+                this.add_paths(&paths);
+            }
+            chooser.destroy();
+        });
+        chooser.show();
+        *self.main_window.native_dialog.borrow_mut() = Some(chooser.upcast());
+    }
+
+    // Add files, or the files in folders, to the files to embed,
+    // showing why any of them couldn't be added.
+    fn add_paths(&self, paths:&[PathBuf]) {
+        let mut failures = Vec::new();
+        for path in paths {
+            self.add_path(path, &mut failures);
+        }
+        self.update();
+        if !failures.is_empty() {
+            self.error_label.set_text(&failures.join("\n"));
+        }
+    }
+
+    // Add a file, or the files in a folder, to the files to embed,
+    // noting why any of them couldn't be added.
+    // Embedded files only have a name, not a path, so files in
+    // subfolders aren't added, and names must be unique.
+    fn add_path(&self, path:&Path, failures:&mut Vec<String>) {
+        if path.is_dir() {
+            match folder_files(path) {
+                Ok(file_paths) => file_paths.iter().for_each(|file_path| self.add_path(file_path, failures)),
+                Err(err) => failures.push(format!("Unable to read folder {}: {}", path.display(), err)),
+            }
+            return;
+        }
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        if self.files.borrow().iter().any(|file| file.name == name) {
+            failures.push(format!("A file named {} has already been added", name));
+            return;
+        }
+        match fs::metadata(path) {
+            Ok(metadata) => self.files.borrow_mut().push(PayloadFile { path: path.to_path_buf(), name, len: metadata.len() }),
+            Err(err) => failures.push(format!("Unable to add {}: {}", path.display(), err)),
+        }
+    }
+
+    // Embedding options from the settings, and the password if there is one.
+    fn embed_options(&self) -> Result<EmbedOptions, StegError> {
+        let mut builder = EmbedOptionsBuilder::from_settings(&self.img_steg.borrow().settings);
+        if self.use_password.is_active() {
+            builder = builder.password(&self.password.text());
+        }
+        builder.build()
+    }
+
+    // Show the files to embed, and how they fit the image,
+    // and only allow embedding when everything checks out.
+    fn update(&self) {
+        while let Some(row) = self.file_list.first_child() {
+            self.file_list.remove(&row);
+        }
+        for (index, file) in self.files.borrow().iter().enumerate() {
+            self.file_list.append(&self.file_row(index, file));
+        }
+
+        let problem = self.check();
+        self.error_label.set_text(problem.as_deref().unwrap_or(""));
+        let ready = problem.is_none() && !self.files.borrow().is_empty();
+        self.dialog.set_response_sensitive(gtk::ResponseType::Accept, ready);
+    }
+
+    // Row of the file list, for one file to embed.
+    fn file_row(&self, index:usize, file:&PayloadFile) -> gtk::Box {
+        let name = gtk::Label::builder()
+            .label(file.name.as_str())
+            .tooltip_text(file.path.to_string_lossy().as_ref())
+            .halign(gtk::Align::Start)
+            .hexpand(true)
+            .ellipsize(gtk::pango::EllipsizeMode::Middle)
+            .build();
+        // Bytes the file takes up in the image, with its name and length.
+        let required = entry_bytes(&PayloadEntry::new(&file.name, io::empty(), file.len));
+        let size = gtk::Label::builder()
+            .label(format!("{} bytes", required))
+            .tooltip_text(format!("{} bytes of file, {} bytes of name and length", file.len, required - file.len))
+            .build();
+        let remove = gtk::Button::builder()
+            .icon_name("list-remove-symbolic")
+            .tooltip_text("Remove file")
+            .build();
+        let this = self.clone();
+        remove.connect_clicked(move |_| {
+            this.files.borrow_mut().remove(index);
+            this.update();
+        });
+
+        let row = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .spacing(6)
+            .margin_start(6)
+            .margin_end(6)
+            .build();
+        row.append(&name);
+        row.append(&size);
+        row.append(&remove);
+        row
+    }
+
+    // Update the capacity meter, and return what is stopping the
+    // files being embedded, if anything.
+    fn check(&self) -> Option<String> {
+        let options = match self.embed_options() {
+            Ok(options) => options,
+            Err(err) => return Some(err.to_string()),
+        };
+        let files = self.files.borrow();
+        let entries:Vec<PayloadEntry> = files.iter().map(|file| PayloadEntry::new(&file.name, io::empty(), file.len)).collect();
+        let img_steg = self.img_steg.borrow();
+        let available = img_steg.usable_capacity(&options);
+        let required = img_steg.required_bytes(&entries, &options);
+
+        // Compressed data can be smaller, so the meter shows the most needed.
+        let compressing = options.compression != Compression::None;
+        // The files take up the bytes shown in their rows, the rest is header.
+        let header = required.saturating_sub(entries.iter().map(entry_bytes).sum());
+        let fraction = if available == 0 { 1.0 } else { required as f64 / available as f64 };
+        self.meter.set_fraction(fraction.min(1.0));
+        self.meter.set_text(Some(&format!(
+            "{}{} of {} bytes ({:.1}%), header {} bytes",
+            if compressing { "Up to " } else { "" },
+            required,
+            available,
+            fraction * 100.0,
+            header
+        )));
+
+        if files.len() > MAX_FILES {
+            return Some(format!("Too many files to embed, at most {} can be embedded", MAX_FILES));
+        }
+        if required > available && !compressing {
+            return Some(StegError::CapacityExceeded { required, available }.to_string());
+        }
+        if self.use_password.is_active() {
+            if self.password.text().is_empty() {
+                return Some(String::from("Enter a password"));
+            }
+            if self.password.text() != self.confirm.text() {
+                return Some(String::from("Passwords do not match"));
+            }
+        }
+        None
+    }
+
+    // Embed the files into the image, on a worker thread.
+    // The dialog is hidden while embedding, and shown again
+    // with the error if embedding fails.
+    fn embed(&self) {
+        let paths:Vec<String> = self.files.borrow().iter().map(|file| file.path.to_string_lossy().into_owned()).collect();
+        let use_password = self.use_password.is_active();
+        let password:Password = Zeroizing::new(self.password.text().to_string());
+        info!("Embedding {} file(s), with password: {}", paths.len(), use_password);
+
+        self.dialog.set_visible(false);
+        let this = self.clone();
+        run_job(
+            &self.main_window,
+            &self.img_steg,
+            "Embedding",
+            move |steg| {
+                let paths:Vec<&str> = paths.iter().map(String::as_str).collect();
+                steg.embed_files(use_password, &password, &paths)
+            },
+            move |result| {
+                // The image is only changed if the files were embedded.
+                match &result {
+                    Ok(()) => refresh_image(&this.main_window, &this.img_steg.borrow()),
+                    Err(_) => refresh_window(&this.main_window, &this.img_steg.borrow()),
+                }
+                match result {
+                    Ok(()) => this.dialog.close(),
+                    Err(err) => {
+                        error!("Unable to embed files: {}", err);
+                        let message = match err {
+                            StegError::Cancelled => String::from("Embedding cancelled, the image is unchanged"),
+                            err => format!("Unable to embed files: {}", err),
+                        };
+                        this.error_label.set_text(&message);
+                        this.dialog.set_visible(true);
+                    }
+                }
+            },
+        );
+    }
+}
+
+// Files in a folder, not including subfolders, in name order.
+fn folder_files(folder:&Path) -> io::Result<Vec<PathBuf>> {
+    let mut file_paths = Vec::new();
+    for dir_entry in fs::read_dir(folder)? {
+        let file_path = dir_entry?.path();
+        if file_path.is_file() {
+            file_paths.push(file_path);
+        }
+    }
+    file_paths.sort();
+    Ok(file_paths)
+}
@@ -0,0 +1,385 @@
+// Extract dialog.
+//
+// Password protected images ask for the password first. The embedded
+// files are then listed, to pick which of them to extract, and where
+// to extract them to, which defaults to the folder last extracted to,
+// or else the settings secret folder.
+
+use log::{error, info};
+use std::cell::RefCell;
+use std::io::Read;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use gtk::{gio, prelude::*};
+use zeroize::Zeroizing;
+
+use hidey::password::Password;
+use hidey::steg::extract::{EntryMetadata, ExtractSink, FolderSink};
+use hidey::steg::report::EntryReport;
+use hidey::steg::{StegError, Steganography};
+
+use crate::ui::dialogs::{show_error, show_info};
+use crate::ui::worker::run_job;
+use crate::ui::{refresh_window, MainWindow};
+
+// Extract dialog widgets, and the files that can be extracted.
+// Widgets are reference counted, so clones refer to the same dialog.
+#[derive(Clone)]
+struct ExtractDialog {
+    dialog: gtk::Dialog,
+    entry_checks: Vec<gtk::CheckButton>,
+    folder_label: gtk::Label,
+    error_label: gtk::Label,
+    folder: Rc<RefCell<PathBuf>>,
+    password: Rc<Password>,
+    main_window: MainWindow,
+    img_steg: Rc<RefCell<Steganography>>,
+}
+
+pub fn extract_from_image(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>) {
+    info!("Extract menu item selected.");
+
+    let (img_to_proc, pic_coded, pic_has_pw) = {
+        let img_steg = img_steg.borrow();
+        (img_steg.img_to_proc, img_steg.pic_coded, img_steg.pic_has_pw)
+    };
+    if !img_to_proc {
+        show_error(&main_window.window, "Unable to extract files", "Open an image to extract the files from first.");
+    }
+    else if !pic_coded {
+        show_error(&main_window.window, "Unable to extract files", &StegError::NotCoded.to_string());
+    }
+    else if pic_has_pw {
+        ask_password(main_window, img_steg);
+    }
+    else {
+        choose_entries(main_window, img_steg, Zeroizing::new(String::new()));
+    }
+}
+
+// Ask for the password of a password protected image,
+// until the right one is given, or the user gives up.
+fn ask_password(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>) {
+    let dialog = gtk::Dialog::builder()
+        .title("Password Required")
+        .transient_for(&main_window.window)
+        .modal(true)
+        .build();
+    dialog.add_button("_Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("_OK", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let password_entry = gtk::PasswordEntry::builder()
+        .show_peek_icon(true)
+        .activates_default(true)
+        .build();
+    let error_label = gtk::Label::builder()
+        .halign(gtk::Align::Start)
+        .css_classes(["error"])
+        .build();
+    let content = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(6)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_top(12)
+        .margin_bottom(12)
+        .build();
+    content.append(&gtk::Label::builder().label("Enter the password for the embedded files.").halign(gtk::Align::Start).build());
+    content.append(&password_entry);
+    content.append(&error_label);
+    dialog.content_area().append(&content);
+
+    let main_window = main_window.clone();
+    let img_steg = img_steg.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response != gtk::ResponseType::Accept {
+            dialog.close();
+            return;
+        }
+        let password:Password = Zeroizing::new(password_entry.text().to_string());
+        let job_password:Password = password.clone();
+
+        // Checking the password is slow on purpose, so it is done on
+        // a worker thread, with the dialog disabled until it is done.
+        dialog.set_sensitive(false);
+        let dialog = dialog.clone();
+        let password_entry = password_entry.clone();
+        let error_label = error_label.clone();
+        let this_window = main_window.clone();
+        let this_steg = img_steg.clone();
+        run_job(
+            &main_window,
+            &img_steg,
+            "Checking password",
+            move |steg| {
+                // Read the header again, up to the password, before checking it.
+                steg.recheck_embedding()?;
+                steg.check_valid_password(job_password.to_string())
+            },
+            move |result| {
+                dialog.set_sensitive(true);
+                match result {
+                    Ok(()) => {
+                        dialog.close();
+                        choose_entries(&this_window, &this_steg, password);
+                    }
+                    Err(StegError::BadPassword) => {
+                        error_label.set_text("Incorrect password, please try again");
+                        password_entry.set_text("");
+                        password_entry.grab_focus();
+                    }
+                    Err(err) => {
+                        error!("Unable to check password: {}", err);
+                        dialog.close();
+                        show_error(&this_window.window, "Unable to check password", &err.to_string());
+                    }
+                }
+            },
+        );
+    });
+    dialog.present();
+}
+
+// List the embedded files, to pick which to extract.
+// Reading the embedded files takes as long as extracting them,
+// so they are listed on a worker thread.
+fn choose_entries(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>, password:Password) {
+    let job_password:Password = password.clone();
+    let this_window = main_window.clone();
+    let this_steg = img_steg.clone();
+    run_job(
+        main_window,
+        img_steg,
+        "Listing embedded files",
+        move |steg| steg.image_report(Some(&job_password)).map(|report| report.entries.unwrap_or_default()),
+        move |result| {
+            let entries = match result {
+                Ok(entries) => entries,
+                Err(err) => {
+                    error!("Unable to list embedded files: {}", err);
+                    show_error(&this_window.window, "Unable to list embedded files", &err.to_string());
+                    return;
+                }
+            };
+            // Embedded files of a password protected image can only be counted now.
+            this_steg.borrow_mut().pic_file_count = Some(entries.len() as u64);
+            refresh_window(&this_window, &this_steg.borrow());
+            if entries.is_empty() {
+                show_info(&this_window.window, "No embedded files", "The image is pic coded, but has no embedded files.");
+                return;
+            }
+            let extract_dialog = ExtractDialog::new(&this_window, &this_steg, password, &entries);
+            extract_dialog.update();
+            extract_dialog.dialog.present();
+        },
+    );
+}
+
+impl ExtractDialog {
+    fn new(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>, password:Password, entries:&[EntryReport]) -> Self {
+        let dialog = gtk::Dialog::builder()
+            .title("Extract Files")
+            .transient_for(&main_window.window)
+            .modal(true)
+            .default_width(480)
+            .default_height(400)
+            .build();
+        dialog.add_button("_Cancel", gtk::ResponseType::Cancel);
+        dialog.add_button("_Extract", gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+
+        // Embedded files, all picked to start with.
+        let entry_list = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .build();
+        let entry_checks:Vec<gtk::CheckButton> = entries
+            .iter()
+            .map(|entry| {
+                gtk::CheckButton::builder()
+                    .label(format!("{}  ({} bytes)", entry.name, entry.size))
+                    .active(true)
+                    .build()
+            })
+            .collect();
+        for entry_check in &entry_checks {
+            entry_list.append(entry_check);
+        }
+        let entry_scroll = gtk::ScrolledWindow::builder()
+            .child(&entry_list)
+            .min_content_height(160)
+            .vexpand(true)
+            .build();
+
+        let select_all_button = gtk::Button::with_mnemonic("Select _All");
+        let select_none_button = gtk::Button::with_mnemonic("Select _None");
+        let select_row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        select_row.append(&select_all_button);
+        select_row.append(&select_none_button);
+
+        // Folder to extract to, the last one used, or else the secret folder.
+        let folder = main_window
+            .state
+            .borrow()
+            .extract_folder
+            .as_ref()
+            .map(PathBuf::from)
+            .filter(|folder| folder.is_dir())
+            .unwrap_or_else(|| PathBuf::from(&img_steg.borrow().settings.secret_folder));
+        let folder_label = gtk::Label::builder()
+            .halign(gtk::Align::Start)
+            .hexpand(true)
+            .ellipsize(gtk::pango::EllipsizeMode::Start)
+            .build();
+        let folder_button = gtk::Button::with_mnemonic("Extract _to…");
+        let folder_row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        folder_row.append(&folder_label);
+        folder_row.append(&folder_button);
+
+        let error_label = gtk::Label::builder()
+            .halign(gtk::Align::Start)
+            .wrap(true)
+            .css_classes(["error"])
+            .build();
+
+        let content = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(6)
+            .margin_start(12)
+            .margin_end(12)
+            .margin_top(12)
+            .margin_bottom(12)
+            .build();
+        content.append(&gtk::Label::builder().label("Files to extract").halign(gtk::Align::Start).build());
+        content.append(&entry_scroll);
+        content.append(&select_row);
+        content.append(&folder_row);
+        content.append(&error_label);
+        dialog.content_area().append(&content);
+
+        let extract_dialog = ExtractDialog {
+            dialog,
+            entry_checks,
+            folder_label,
+            error_label,
+            folder: Rc::new(RefCell::new(folder)),
+            password: Rc::new(password),
+            main_window: main_window.clone(),
+            img_steg: img_steg.clone(),
+        };
+
+        for entry_check in &extract_dialog.entry_checks {
+            let this = extract_dialog.clone();
+            entry_check.connect_toggled(move |_| this.update());
+        }
+        let this = extract_dialog.clone();
+        select_all_button.connect_clicked(move |_| this.entry_checks.iter().for_each(|check| check.set_active(true)));
+        let this = extract_dialog.clone();
+        select_none_button.connect_clicked(move |_| this.entry_checks.iter().for_each(|check| check.set_active(false)));
+        let this = extract_dialog.clone();
+        folder_button.connect_clicked(move |_| this.choose_folder());
+
+        let this = extract_dialog.clone();
+        extract_dialog.dialog.connect_response(move |dialog, response| {
+            if response != gtk::ResponseType::Accept {
+                dialog.close();
+                return;
+            }
+            this.extract();
+        });
+        extract_dialog
+    }
+
+    // Show the folder to extract to, and only allow extracting
+    // once at least one file is picked.
+    fn update(&self) {
+        self.folder_label.set_text(&self.folder.borrow().to_string_lossy());
+        let any_picked = self.entry_checks.iter().any(|check| check.is_active());
+        self.dialog.set_response_sensitive(gtk::ResponseType::Accept, any_picked);
+    }
+
+    // Pick the folder to extract to.
+    fn choose_folder(&self) {
+        let chooser = gtk::FileChooserNative::new(
+            Some("Extract To"),
+            Some(&self.dialog),
+            gtk::FileChooserAction::SelectFolder,
+            Some("_Select"),
+            Some("_Cancel"),
+        );
+        chooser.set_modal(true);
+        if self.folder.borrow().is_dir() {
+            let _ = chooser.set_current_folder(Some(&gio::File::for_path(&*self.folder.borrow())));
+        }
+
+        let this = self.clone();
+        chooser.connect_response(move |chooser, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = chooser.file().and_then(|file| file.path()) {
+                    *this.folder.borrow_mut() = path;
+                    this.update();
+                }
+            }
+            chooser.destroy();
+        });
+        chooser.show();
+        *self.main_window.native_dialog.borrow_mut() = Some(chooser.upcast());
+    }
+
+    // Extract the picked files, on a worker thread.
+    // The dialog is hidden while extracting, and shown again
+    // with the error if extracting fails.
+    fn extract(&self) {
+        let picked:Vec<bool> = self.entry_checks.iter().map(|check| check.is_active()).collect();
+        let folder = self.folder.borrow().clone();
+        let password:Password = (*self.password).clone();
+        info!("Extracting files to: {}", folder.display());
+
+        self.dialog.set_visible(false);
+        let this = self.clone();
+        run_job(
+            &self.main_window,
+            &self.img_steg,
+            "Extracting",
+            move |steg| {
+                let mut folder_sink = FolderSink { folder, written: Vec::new() };
+                let mut sink = |name: &str, metadata: &EntryMetadata, data: &mut dyn Read| {
+                    if picked.get(metadata.index).copied().unwrap_or(false) {
+                        folder_sink.extract_entry(name, metadata, data)
+                    } else {
+                        Ok(())
+                    }
+                };
+                steg.extract_to_sink(&password, &mut sink)?;
+                Ok(folder_sink.written)
+            },
+            move |result| match result {
+                Ok(written) => {
+                    this.main_window.state.borrow_mut().extract_folder = Some(this.folder.borrow().to_string_lossy().into_owned());
+                    this.dialog.close();
+                    this.show_summary(&written);
+                }
+                Err(err) => {
+                    error!("Unable to extract files: {}", err);
+                    let message = match err {
+                        StegError::Cancelled => String::from("Extracting cancelled, files already fully extracted have been kept"),
+                        err => format!("Unable to extract files: {}", err),
+                    };
+                    this.error_label.set_text(&message);
+                    this.dialog.set_visible(true);
+                }
+            },
+        );
+    }
+
+    // Show what was written.
+    fn show_summary(&self, written:&[PathBuf]) {
+        let message = format!("Extracted {} file(s)", written.len());
+        let mut detail = format!("To: {}\n", self.folder.borrow().display());
+        for path in written {
+            detail.push_str(&format!("\n{}", path.file_name().unwrap_or_default().to_string_lossy()));
+        }
+        show_info(&self.main_window.window, &message, &detail);
+    }
+}
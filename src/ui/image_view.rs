@@ -0,0 +1,312 @@
+// Image display pane.
+//
+// The loaded image is drawn on a scrolled canvas, either fitted to the
+// pane or at a zoom level, and can be panned by dragging it.
+// Hovering over the image shows the pixel under the pointer, with the
+// least significant bit of each colour plane, where embedded data starts.
+
+use log::warn;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use gtk::{cairo, glib, prelude::*};
+use image::{DynamicImage, GenericImageView};
+
+// Zoom limits, and the factor for each zoom in or out.
+const MIN_ZOOM: f64 = 0.05;
+const MAX_ZOOM: f64 = 32.0;
+const ZOOM_STEP: f64 = 1.25;
+
+// Image being shown, and how it is drawn.
+struct ViewState {
+    image: Option<DynamicImage>,
+    surface: Option<cairo::ImageSurface>,
+    // Fit the image to the pane, rather than using the zoom level.
+    fit: bool,
+    zoom: f64,
+    // Scale and offset the image was last drawn with, to map the
+    // pointer back to image pixels.
+    scale: f64,
+    offset: (f64, f64),
+}
+
+// Image pane, with its zoom controls and pixel inspector.
+// Widgets are reference counted, so clones refer to the same pane.
+#[derive(Clone)]
+pub struct ImageView {
+    pub widget: gtk::Box,
+    area: gtk::DrawingArea,
+    zoom_label: gtk::Label,
+    pixel_label: gtk::Label,
+    state: Rc<RefCell<ViewState>>,
+}
+
+impl ImageView {
+    pub fn new() -> Self {
+        let area = gtk::DrawingArea::builder()
+            .hexpand(true)
+            .vexpand(true)
+            .build();
+        let scrolled = gtk::ScrolledWindow::builder()
+            .child(&area)
+            .hexpand(true)
+            .vexpand(true)
+            .build();
+
+        // Zoom controls, and pixel inspector, above the image.
+        let fit_button = gtk::Button::builder().label("Fit").tooltip_text("Fit image to window").build();
+        let actual_button = gtk::Button::builder().label("100%").tooltip_text("Actual size").build();
+        let zoom_out_button = gtk::Button::builder().icon_name("zoom-out-symbolic").tooltip_text("Zoom out").build();
+        let zoom_in_button = gtk::Button::builder().icon_name("zoom-in-symbolic").tooltip_text("Zoom in").build();
+        let zoom_label = gtk::Label::builder().width_chars(6).build();
+        let pixel_label = gtk::Label::builder()
+            .halign(gtk::Align::End)
+            .hexpand(true)
+            .selectable(true)
+            .build();
+
+        let toolbar = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .spacing(6)
+            .margin_start(6)
+            .margin_end(6)
+            .margin_top(3)
+            .margin_bottom(3)
+            .build();
+        toolbar.append(&fit_button);
+        toolbar.append(&actual_button);
+        toolbar.append(&zoom_out_button);
+        toolbar.append(&zoom_in_button);
+        toolbar.append(&zoom_label);
+        toolbar.append(&pixel_label);
+
+        let widget = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        widget.append(&toolbar);
+        widget.append(&scrolled);
+
+        let state = ViewState {
+            image: None,
+            surface: None,
+            fit: true,
+            zoom: 1.0,
+            scale: 1.0,
+            offset: (0.0, 0.0),
+        };
+        let view = ImageView {
+            widget,
+            area,
+            zoom_label,
+            pixel_label,
+            state: Rc::new(RefCell::new(state)),
+        };
+
+        view.connect_draw();
+        view.connect_inspector();
+        view.connect_pan(&scrolled);
+        view.connect_scroll_zoom(&scrolled);
+
+        let view_clone = view.clone();
+        fit_button.connect_clicked(move |_| view_clone.set_fit());
+        let view_clone = view.clone();
+        actual_button.connect_clicked(move |_| view_clone.set_zoom(1.0));
+        let view_clone = view.clone();
+        zoom_out_button.connect_clicked(move |_| view_clone.zoom_by(1.0 / ZOOM_STEP));
+        let view_clone = view.clone();
+        zoom_in_button.connect_clicked(move |_| view_clone.zoom_by(ZOOM_STEP));
+
+        view.set_fit();
+        view
+    }
+
+    // Show an image, or clear the pane.
+    // The current fit or zoom level is kept.
+    pub fn set_image(&self, image:Option<&DynamicImage>) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.image = image.cloned();
+            state.surface = image.and_then(image_surface);
+        }
+        self.pixel_label.set_text("");
+        if self.state.borrow().fit {
+            self.set_fit();
+        } else {
+            let zoom = self.state.borrow().zoom;
+            self.set_zoom(zoom);
+        }
+    }
+
+    // Fit the image to the pane.
+    pub fn set_fit(&self) {
+        self.state.borrow_mut().fit = true;
+        self.area.set_content_width(0);
+        self.area.set_content_height(0);
+        self.zoom_label.set_text("Fit");
+        self.area.queue_draw();
+    }
+
+    // Show the image at a zoom level, scrolling if it doesn't fit.
+    pub fn set_zoom(&self, zoom:f64) {
+        let zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        let mut state = self.state.borrow_mut();
+        state.fit = false;
+        state.zoom = zoom;
+        let (width, height) = state.image.as_ref().map(|image| image.dimensions()).unwrap_or((0, 0));
+        self.area.set_content_width((width as f64 * zoom).round() as i32);
+        self.area.set_content_height((height as f64 * zoom).round() as i32);
+        self.zoom_label.set_text(&format!("{:.0}%", zoom * 100.0));
+        self.area.queue_draw();
+    }
+
+    // Zoom in or out from the scale currently shown.
+    pub fn zoom_by(&self, factor:f64) {
+        let scale = {
+            let state = self.state.borrow();
+            if state.fit && state.scale > 0.0 { state.scale } else { state.zoom }
+        };
+        self.set_zoom(scale * factor);
+    }
+
+    // Draw the image, centred when it is smaller than the pane.
+    fn connect_draw(&self) {
+        let state = self.state.clone();
+        self.area.set_draw_func(move |_, cr, width, height| {
+            let mut state = state.borrow_mut();
+            let Some(surface) = state.surface.clone() else {
+                return;
+            };
+            let (image_width, image_height) = (surface.width() as f64, surface.height() as f64);
+            let scale = if state.fit {
+                (width as f64 / image_width).min(height as f64 / image_height)
+            } else {
+                state.zoom
+            };
+            if scale <= 0.0 {
+                return;
+            }
+            let offset = (
+                ((width as f64 - image_width * scale) / 2.0).max(0.0),
+                ((height as f64 - image_height * scale) / 2.0).max(0.0),
+            );
+            state.scale = scale;
+            state.offset = offset;
+
+            cr.translate(offset.0, offset.1);
+            cr.scale(scale, scale);
+            if let Err(err) = cr.set_source_surface(&surface, 0.0, 0.0) {
+                warn!("Unable to draw image: {}", err);
+                return;
+            }
+            // Show individual pixels when zoomed in, rather than blurring them.
+            if scale >= 1.0 {
+                cr.source().set_filter(cairo::Filter::Nearest);
+            }
+            if let Err(err) = cr.paint() {
+                warn!("Unable to draw image: {}", err);
+            }
+        });
+    }
+
+    // Show the pixel under the pointer.
+    fn connect_inspector(&self) {
+        let motion = gtk::EventControllerMotion::new();
+        let view = self.clone();
+        motion.connect_motion(move |_, x, y| {
+            let state = view.state.borrow();
+            let text = match &state.image {
+                Some(image) if state.scale > 0.0 => {
+                    let pixel_x = ((x - state.offset.0) / state.scale).floor();
+                    let pixel_y = ((y - state.offset.1) / state.scale).floor();
+                    let (width, height) = image.dimensions();
+                    if pixel_x >= 0.0 && pixel_y >= 0.0 && pixel_x < width as f64 && pixel_y < height as f64 {
+                        pixel_text(image, pixel_x as u32, pixel_y as u32)
+                    } else {
+                        String::new()
+                    }
+                }
+                _ => String::new(),
+            };
+            view.pixel_label.set_text(&text);
+        });
+        let pixel_label = self.pixel_label.clone();
+        motion.connect_leave(move |_| pixel_label.set_text(""));
+        self.area.add_controller(motion);
+    }
+
+    // Pan the image by dragging it.
+    // The drag is tracked on the scrolled window, which stays put
+    // while the image moves under the pointer.
+    fn connect_pan(&self, scrolled:&gtk::ScrolledWindow) {
+        let drag = gtk::GestureDrag::new();
+        let start = Rc::new(Cell::new((0.0, 0.0)));
+
+        let scrolled_clone = scrolled.clone();
+        let start_clone = start.clone();
+        drag.connect_drag_begin(move |_, _, _| {
+            start_clone.set((scrolled_clone.hadjustment().value(), scrolled_clone.vadjustment().value()));
+        });
+        let scrolled_clone = scrolled.clone();
+        drag.connect_drag_update(move |_, offset_x, offset_y| {
+            let (start_x, start_y) = start.get();
+            scrolled_clone.hadjustment().set_value(start_x - offset_x);
+            scrolled_clone.vadjustment().set_value(start_y - offset_y);
+        });
+        scrolled.add_controller(drag);
+    }
+
+    // Zoom with the scroll wheel while control is held.
+    fn connect_scroll_zoom(&self, scrolled:&gtk::ScrolledWindow) {
+        let scroll = gtk::EventControllerScroll::new(gtk::EventControllerScrollFlags::VERTICAL);
+        let view = self.clone();
+        scroll.connect_scroll(move |scroll, _, delta_y| {
+            if !scroll.current_event_state().contains(gtk::gdk::ModifierType::CONTROL_MASK) {
+                return glib::Propagation::Proceed;
+            }
+            view.zoom_by(if delta_y < 0.0 { ZOOM_STEP } else { 1.0 / ZOOM_STEP });
+            glib::Propagation::Stop
+        });
+        scrolled.add_controller(scroll);
+    }
+}
+
+// Pixel values, and the least significant bit of each colour plane.
+fn pixel_text(image:&DynamicImage, x:u32, y:u32) -> String {
+    let [red, green, blue, alpha] = image.get_pixel(x, y).0;
+    let mut text = format!(
+        "({}, {})  R {} (lsb {})  G {} (lsb {})  B {} (lsb {})",
+        x,
+        y,
+        red,
+        red & 1,
+        green,
+        green & 1,
+        blue,
+        blue & 1
+    );
+    if image.color().has_alpha() {
+        text.push_str(&format!("  A {}", alpha));
+    }
+    text
+}
+
+// Cairo surface holding a copy of an image, for drawing.
+fn image_surface(image:&DynamicImage) -> Option<cairo::ImageSurface> {
+    let rgba = image.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let stride = cairo::Format::ARgb32.stride_for_width(width).ok()? as usize;
+    let mut data = vec![0u8; stride * height as usize];
+    for (x, y, pixel) in rgba.enumerate_pixels() {
+        // Cairo wants premultiplied alpha, in native endian words.
+        let [red, green, blue, alpha] = pixel.0;
+        let premultiply = |value:u8| ((value as u32 * alpha as u32 + 127) / 255) as u8;
+        let word = u32::from_be_bytes([alpha, premultiply(red), premultiply(green), premultiply(blue)]);
+        let at = y as usize * stride + x as usize * 4;
+        data[at..at + 4].copy_from_slice(&word.to_ne_bytes());
+    }
+    match cairo::ImageSurface::create_for_data(data, cairo::Format::ARgb32, width as i32, height as i32, stride as i32) {
+        Ok(surface) => Some(surface),
+        Err(err) => {
+            warn!("Unable to create image surface: {}", err);
+            None
+        }
+    }
+}
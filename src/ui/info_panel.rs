@@ -0,0 +1,133 @@
+// Image information panel.
+//
+// Side panel, that can be hidden, listing what is known about the
+// loaded image and the files embedded in it. The same facts are
+// summarised in the status bar below the image.
+
+use std::path::Path;
+
+use gtk::prelude::*;
+
+use hidey::steg::Steganography;
+
+// Names of the facts shown, in the order they are shown.
+const FACT_NAMES: [&str; 9] = [
+    "File",
+    "Dimensions",
+    "Colour planes",
+    "Capacity",
+    "Pic coded",
+    "Password",
+    "Container",
+    "Embedded files",
+    "Unsaved changes",
+];
+
+// Information panel widgets.
+// Widgets are reference counted, so clones refer to the same panel.
+#[derive(Clone)]
+pub struct InfoPanel {
+    pub revealer: gtk::Revealer,
+    values: Vec<gtk::Label>,
+}
+
+impl InfoPanel {
+    pub fn new() -> Self {
+        let grid = gtk::Grid::builder()
+            .row_spacing(6)
+            .column_spacing(12)
+            .margin_start(12)
+            .margin_end(12)
+            .margin_top(12)
+            .margin_bottom(12)
+            .build();
+        let mut values = Vec::with_capacity(FACT_NAMES.len());
+        for (row, name) in FACT_NAMES.iter().enumerate() {
+            let name_label = gtk::Label::builder()
+                .label(*name)
+                .halign(gtk::Align::Start)
+                .css_classes(["dim-label"])
+                .build();
+            let value_label = gtk::Label::builder()
+                .halign(gtk::Align::Start)
+                .selectable(true)
+                .wrap(true)
+                .max_width_chars(24)
+                .build();
+            grid.attach(&name_label, 0, row as i32, 1, 1);
+            grid.attach(&value_label, 1, row as i32, 1, 1);
+            values.push(value_label);
+        }
+
+        let revealer = gtk::Revealer::builder()
+            .transition_type(gtk::RevealerTransitionType::SlideLeft)
+            .reveal_child(true)
+            .child(&grid)
+            .build();
+        InfoPanel { revealer, values }
+    }
+
+    // Show the facts about the loaded image.
+    pub fn update(&self, img_steg:&Steganography) {
+        let facts = image_facts(img_steg);
+        for (value_label, fact) in self.values.iter().zip(facts) {
+            value_label.set_text(&fact);
+        }
+    }
+}
+
+// Facts about the loaded image, in the order of FACT_NAMES.
+fn image_facts(img_steg:&Steganography) -> [String; 9] {
+    if !img_steg.img_to_proc {
+        return Default::default();
+    }
+    let file_name = Path::new(&img_steg.image_file).file_name().unwrap_or_default().to_string_lossy().into_owned();
+    [
+        file_name,
+        format!("{} x {}", img_steg.pic_width, img_steg.pic_height),
+        img_steg.pic_col_planes.to_string(),
+        format!("{} bytes", img_steg.embed_capacity),
+        yes_no(img_steg.pic_coded).to_string(),
+        if img_steg.pic_coded { yes_no(img_steg.pic_has_pw).to_string() } else { String::from("-") },
+        container_text(img_steg),
+        file_count_text(img_steg),
+        yes_no(img_steg.img_changed).to_string(),
+    ]
+}
+
+pub fn yes_no(flag:bool) -> &'static str {
+    if flag { "yes" } else { "no" }
+}
+
+// Container version, and how the files are embedded in it.
+pub fn container_text(img_steg:&Steganography) -> String {
+    if !img_steg.pic_coded {
+        return String::from("-");
+    }
+    let container = &img_steg.container;
+    let mut features = Vec::new();
+    if container.encrypted {
+        features.push(String::from("encrypted"));
+    }
+    if container.compressed {
+        features.push(String::from("compressed"));
+    }
+    if container.fec_copies > 1 {
+        features.push(format!("{} copies", container.fec_copies));
+    }
+    if features.is_empty() {
+        format!("v{}", container.version)
+    } else {
+        format!("v{} ({})", container.version, features.join(", "))
+    }
+}
+
+// Number of embedded files, if it is known.
+pub fn file_count_text(img_steg:&Steganography) -> String {
+    match img_steg.pic_file_count {
+        _ if !img_steg.pic_coded => String::from("-"),
+        Some(count) => count.to_string(),
+        None if img_steg.pic_has_pw => String::from("needs password"),
+        None => String::from("unknown"),
+    }
+}
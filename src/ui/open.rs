@@ -0,0 +1,123 @@
+// File open menu handling.
+//
+// Images are chosen with the native file chooser, limited to the
+// lossless image formats that can hold embedded files, or from the
+// recently opened images in the File menu.
+
+use log::{error, info, warn};
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use gtk::{gio, prelude::*};
+
+use hidey::steg::Steganography;
+
+use crate::ui::dialogs::show_error;
+use crate::ui::save::confirm_unsaved;
+use crate::ui::{refresh_image, MainWindow};
+
+// Lossless image file extensions that can be opened.
+pub const IMAGE_SUFFIXES: [&str; 4] = ["png", "bmp", "tif", "tiff"];
+
+// Filter for lossless image files.
+pub fn image_filter() -> gtk::FileFilter {
+    let filter = gtk::FileFilter::new();
+    filter.set_name(Some("Lossless images (png, bmp, tiff)"));
+    for suffix in IMAGE_SUFFIXES {
+        filter.add_suffix(suffix);
+    }
+    filter
+}
+
+pub fn open_image(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>) {
+    info!("Open image menu item selected.");
+
+    let dialog = gtk::FileChooserNative::new(
+        Some("Open Image"),
+        Some(&main_window.window),
+        gtk::FileChooserAction::Open,
+        Some("_Open"),
+        Some("_Cancel"),
+    );
+    dialog.set_modal(true);
+    dialog.add_filter(&image_filter());
+
+    // Start in the image folder from the settings.
+    let thumb_folder = img_steg.borrow().settings.thumb_folder.clone();
+    if let Err(err) = dialog.set_current_folder(Some(&gio::File::for_path(&thumb_folder))) {
+        warn!("Unable to start in image folder: {}, {}", thumb_folder, err);
+    }
+
+    let main_window_clone = main_window.clone();
+    let img_steg = img_steg.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == gtk::ResponseType::Accept {
+            if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                load_image(&main_window_clone, &img_steg, &path);
+            }
+        }
+        dialog.destroy();
+    });
+    dialog.show();
+    *main_window.native_dialog.borrow_mut() = Some(dialog.upcast());
+}
+
+// Load an image, once any unsaved embedding has been saved or
+// discarded, and show it and its status in the window.
+pub fn load_image(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>, path: &Path) {
+    let main_window_clone = main_window.clone();
+    let img_steg_clone = img_steg.clone();
+    let path = path.to_path_buf();
+    confirm_unsaved(
+        main_window,
+        img_steg,
+        "Save the image before opening another?",
+        "Open _without Saving",
+        move || load_new_image(&main_window_clone, &img_steg_clone, &path),
+    );
+}
+
+// Load an image, and show it and its status in the window.
+// Problems loading the image are shown in an error dialog.
+fn load_new_image(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>, path: &Path) {
+    info!("Loading image: {}", path.display());
+    let result = img_steg.borrow_mut().load_image_file(path);
+    if let Err(err) = &result {
+        error!("Unable to open image: {}, {}", path.display(), err);
+    }
+    if result.is_ok() {
+        main_window.state.borrow_mut().add_recent_file(&path.to_string_lossy());
+        refresh_recent_menu(main_window);
+    }
+    refresh_image(main_window, &img_steg.borrow());
+    if let Err(err) = result {
+        show_error(&main_window.window, "Unable to open image", &format!("{}\n\n{}", path.display(), err));
+    }
+}
+
+// Open a recently opened image, forgetting it if it has gone.
+pub fn open_recent_image(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>, image_file:&str) {
+    info!("Recent image selected: {}", image_file);
+    let path = Path::new(image_file);
+    if !path.is_file() {
+        main_window.state.borrow_mut().remove_recent_file(image_file);
+        refresh_recent_menu(main_window);
+        show_error(&main_window.window, "Unable to open image", &format!("{}\n\nThe image no longer exists.", image_file));
+        return;
+    }
+    load_image(main_window, img_steg, path);
+}
+
+// List the recently opened images in the File menu.
+pub fn refresh_recent_menu(main_window: &MainWindow) {
+    let recent_menu = &main_window.recent_menu;
+    recent_menu.remove_all();
+    for image_file in &main_window.state.borrow().recent_files {
+        // Underscores in file names would be taken as mnemonics.
+        let label = Path::new(image_file).file_name().unwrap_or_default().to_string_lossy().replace('_', "__");
+        let item = gio::MenuItem::new(Some(&label), None);
+        item.set_action_and_target_value(Some("app.open_recent"), Some(&image_file.to_variant()));
+        recent_menu.append_item(&item);
+    }
+}
@@ -0,0 +1,106 @@
+// Embedding preview window.
+//
+// The image before embedding, the embedded image, and a heatmap of the
+// changed colour samples can be switched between, along with how many
+// samples were changed, to judge how visible embedding is before saving.
+// Pixels whose transparency was changed are warned about.
+
+use log::{error, info};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::{gdk, glib, prelude::*};
+use image::DynamicImage;
+
+use hidey::steg::preview::image_difference;
+use hidey::steg::{StegError, Steganography};
+
+use crate::ui::dialogs::show_error;
+use crate::ui::MainWindow;
+
+pub fn preview_image(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>) {
+    info!("Preview menu item selected.");
+
+    let img_steg = img_steg.borrow();
+    let result = img_steg.load_saved_image().and_then(|cover| {
+        let stego = img_steg.image.as_ref().ok_or(StegError::NoImage)?;
+        let difference = image_difference(&cover, stego)?;
+        Ok((cover, stego, difference))
+    });
+    let (cover, stego, difference) = match result {
+        Ok(preview) => preview,
+        Err(err) => {
+            error!("Unable to preview embedding: {}", err);
+            show_error(&main_window.window, "Unable to preview embedding", &err.to_string());
+            return;
+        }
+    };
+
+    // One page for each image, switched between above them.
+    let stack = gtk::Stack::builder()
+        .hexpand(true)
+        .vexpand(true)
+        .build();
+    stack.add_titled(&preview_picture(&cover), Some("cover"), "Before Embedding");
+    stack.add_titled(&preview_picture(stego), Some("stego"), "Embedded");
+    stack.add_titled(&preview_picture(&DynamicImage::ImageRgb8(difference.heatmap.clone())), Some("difference"), "Difference");
+    let switcher = gtk::StackSwitcher::builder()
+        .stack(&stack)
+        .halign(gtk::Align::Center)
+        .build();
+
+    let summary = if img_steg.img_changed {
+        let mut summary = format!(
+            "Changed samples: {} of {} ({:.2}%)",
+            difference.changed_samples,
+            difference.total_samples,
+            difference.changed_percent()
+        );
+        // Embedding makes pixels opaque, which can show more than the colour changes.
+        if difference.changed_alpha > 0 {
+            summary.push_str(&format!(
+                "\nWarning: transparency changed for {} pixels, which embedding has made opaque",
+                difference.changed_alpha
+            ));
+        }
+        summary
+    } else {
+        String::from("No changes since the image was last saved")
+    };
+    let summary_label = gtk::Label::new(Some(&summary));
+
+    let content = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(6)
+        .margin_start(6)
+        .margin_end(6)
+        .margin_top(6)
+        .margin_bottom(6)
+        .build();
+    content.append(&switcher);
+    content.append(&stack);
+    content.append(&summary_label);
+
+    let window = gtk::Window::builder()
+        .title("Embedding Preview")
+        .transient_for(&main_window.window)
+        .default_width(800)
+        .default_height(600)
+        .child(&content)
+        .build();
+    // Open on the changes, as that is what the preview is for.
+    stack.set_visible_child_name("difference");
+    window.present();
+}
+
+// Picture of an image, scaled to fit the preview window.
+fn preview_picture(image:&DynamicImage) -> gtk::Picture {
+    let rgba = image.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let bytes = glib::Bytes::from_owned(rgba.into_raw());
+    let texture = gdk::MemoryTexture::new(width as i32, height as i32, gdk::MemoryFormat::R8g8b8a8, &bytes, width as usize * 4);
+    gtk::Picture::builder()
+        .paintable(&texture)
+        .can_shrink(true)
+        .build()
+}
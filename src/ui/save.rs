@@ -0,0 +1,172 @@
+// File save menu handling.
+//
+// Save overwrites the loaded image, once confirmed, and Save As writes
+// a new image. Either way only lossless formats can be saved to, as
+// lossy formats would lose the embedded files.
+// Closing the window, or opening another image, with embedded files
+// that haven't been saved asks whether to save them first.
+
+use log::{error, info};
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use gtk::{gio, prelude::*};
+
+use hidey::steg::options::OutputFormat;
+use hidey::steg::{StegError, Steganography};
+
+use crate::ui::dialogs::{confirm, show_error};
+use crate::ui::open::image_filter;
+use crate::ui::{refresh_window, MainWindow};
+
+// Save over the loaded image, then do whatever follows saving.
+pub fn save_image(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>, on_saved: impl Fn() + 'static) {
+    info!("Save menu item selected.");
+
+    let (img_to_proc, image_file) = {
+        let img_steg = img_steg.borrow();
+        (img_steg.img_to_proc, img_steg.image_file.clone())
+    };
+    if !img_to_proc {
+        show_error(&main_window.window, "Unable to save image", &StegError::NoImage.to_string());
+        return;
+    }
+    // An image loaded from a lossy format has to be saved as a lossless one.
+    if OutputFormat::from_path(&image_file).is_err() {
+        save_image_as(main_window, img_steg, on_saved);
+        return;
+    }
+
+    let file_name = Path::new(&image_file).file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let main_window_clone = main_window.clone();
+    let img_steg = img_steg.clone();
+    confirm(
+        &main_window.window,
+        &format!("Overwrite {}?", file_name),
+        "The original image will be replaced. Use Save As to keep the original image.",
+        "_Overwrite",
+        move || {
+            if write_image(&main_window_clone, &img_steg, &image_file) {
+                on_saved();
+            }
+        },
+    );
+}
+
+// Save to a new image file, then do whatever follows saving.
+// The file chooser asks before replacing an existing file.
+pub fn save_image_as(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>, on_saved: impl Fn() + 'static) {
+    info!("Save As menu item selected.");
+
+    if !img_steg.borrow().img_to_proc {
+        show_error(&main_window.window, "Unable to save image", &StegError::NoImage.to_string());
+        return;
+    }
+
+    let chooser = gtk::FileChooserNative::new(
+        Some("Save Image As"),
+        Some(&main_window.window),
+        gtk::FileChooserAction::Save,
+        Some("_Save"),
+        Some("_Cancel"),
+    );
+    chooser.set_modal(true);
+    chooser.add_filter(&image_filter());
+
+    // Start next to the loaded image, as a png of the same name.
+    let image_path = Path::new(&img_steg.borrow().image_file).to_path_buf();
+    if let Some(folder) = image_path.parent() {
+        let _ = chooser.set_current_folder(Some(&gio::File::for_path(folder)));
+    }
+    let stem = image_path.file_stem().unwrap_or_default().to_string_lossy();
+    chooser.set_current_name(&format!("{}.png", stem));
+
+    let main_window_clone = main_window.clone();
+    let img_steg = img_steg.clone();
+    chooser.connect_response(move |chooser, response| {
+        if response == gtk::ResponseType::Accept {
+            if let Some(path) = chooser.file().and_then(|file| file.path()) {
+                if write_image(&main_window_clone, &img_steg, &path.to_string_lossy()) {
+                    on_saved();
+                }
+            }
+        }
+        chooser.destroy();
+    });
+    chooser.show();
+    *main_window.native_dialog.borrow_mut() = Some(chooser.upcast());
+}
+
+// Write the image to a file, which becomes the loaded image file.
+// Returns whether the image was saved.
+fn write_image(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>, save_file: &str) -> bool {
+    let result = img_steg.borrow_mut().save_image(save_file);
+    match result {
+        Ok(()) => {
+            img_steg.borrow_mut().image_file = save_file.to_string();
+            refresh_window(main_window, &img_steg.borrow());
+            true
+        }
+        Err(err) => {
+            error!("Unable to save image: {}, {}", save_file, err);
+            show_error(&main_window.window, "Unable to save image", &format!("{}\n\n{}", save_file, err));
+            false
+        }
+    }
+}
+
+// Ask whether to save embedded files that haven't been saved,
+// before the window is closed.
+pub fn confirm_close(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>) {
+    let window = main_window.window.clone();
+    confirm_unsaved(main_window, img_steg, "Save the image before closing?", "Close _without Saving", move || window.close());
+}
+
+// Ask whether to save embedded files that haven't been saved, before
+// they would be lost, then do whatever follows, unless cancelled.
+// Goes straight on if there is nothing unsaved.
+pub fn confirm_unsaved(
+    main_window: &MainWindow,
+    img_steg: &Rc<RefCell<Steganography>>,
+    question: &str,
+    discard_label: &str,
+    on_done: impl Fn() + 'static,
+) {
+    if !img_steg.borrow().img_changed {
+        on_done();
+        return;
+    }
+
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(&main_window.window)
+        .modal(true)
+        .message_type(gtk::MessageType::Question)
+        .buttons(gtk::ButtonsType::None)
+        .text(question)
+        .secondary_text("Files have been embedded in the image since it was last saved, and will be lost.")
+        .build();
+    dialog.add_button(discard_label, gtk::ResponseType::Reject);
+    dialog.add_button("_Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Save _As…", gtk::ResponseType::Other(1));
+    dialog.add_button("_Save", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let main_window = main_window.clone();
+    let img_steg = img_steg.clone();
+    let on_done = Rc::new(on_done);
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+        let on_done = on_done.clone();
+        match response {
+            gtk::ResponseType::Reject => {
+                img_steg.borrow_mut().img_changed = false;
+                on_done();
+            }
+            gtk::ResponseType::Accept => save_image(&main_window, &img_steg, move || on_done()),
+            gtk::ResponseType::Other(1) => save_image_as(&main_window, &img_steg, move || on_done()),
+            _ => (),
+        }
+    });
+    dialog.present();
+}
@@ -0,0 +1,89 @@
+// Saved user interface state.
+//
+// What the user was last doing is remembered between runs, in a state
+// file under the XDG state directory, $XDG_STATE_HOME, which defaults
+// to $HOME/.local/state. The state file is read at start up and
+// written back when the window is closed.
+// Unlike the settings file the state file isn't meant to be edited.
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+// Most recently opened images to remember.
+const MAX_RECENT_FILES: usize = 10;
+
+// User interface state, any of which may be missing from a state file.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuiState {
+    pub window_width: Option<i32>,
+    pub window_height: Option<i32>,
+    pub window_maximized: bool,
+    // Most recently opened image first.
+    pub recent_files: Vec<String>,
+    // Folders files were last picked from to embed, and extracted to.
+    pub embed_folder: Option<String>,
+    pub extract_folder: Option<String>,
+}
+
+impl GuiState {
+    // Read the state file, or start afresh if there isn't one,
+    // or it can't be read.
+    pub fn load() -> Self {
+        let Some(state_file) = state_file() else {
+            return GuiState::default();
+        };
+        match fs::read_to_string(&state_file) {
+            Ok(contents) => serde_yaml::from_str(&contents).unwrap_or_else(|err| {
+                warn!("Ignoring unreadable state file: {}, {}", state_file.display(), err);
+                GuiState::default()
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => GuiState::default(),
+            Err(err) => {
+                warn!("Unable to read state file: {}, {}", state_file.display(), err);
+                GuiState::default()
+            }
+        }
+    }
+
+    // Write the state file, replacing the previous one in one go.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(state_file) = state_file() else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "No home or XDG state directory"));
+        };
+        if let Some(folder) = state_file.parent() {
+            fs::create_dir_all(folder)?;
+        }
+        let contents = serde_yaml::to_string(self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let temp_file = state_file.with_extension("yml.tmp");
+        fs::write(&temp_file, contents)?;
+        fs::rename(&temp_file, &state_file)?;
+        info!("State saved to: {}", state_file.display());
+        Ok(())
+    }
+
+    // Put an image at the top of the recently opened images.
+    pub fn add_recent_file(&mut self, image_file: &str) {
+        self.recent_files.retain(|recent_file| recent_file != image_file);
+        self.recent_files.insert(0, image_file.to_string());
+        self.recent_files.truncate(MAX_RECENT_FILES);
+    }
+
+    pub fn remove_recent_file(&mut self, image_file: &str) {
+        self.recent_files.retain(|recent_file| recent_file != image_file);
+    }
+}
+
+// Path of the state file, if there is somewhere to keep it.
+// Relative XDG paths are ignored, as the XDG specification requires.
+fn state_file() -> Option<PathBuf> {
+    let state_home = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))?;
+    Some(state_home.join("hidey").join("state.yml"))
+}
@@ -0,0 +1,140 @@
+// Background jobs.
+//
+// Embedding and extracting run on a worker thread, so that the window
+// keeps responding. The Steganography instance is moved to the worker
+// for the job, and handed back when the job is done, even if the job
+// panics. Progress is sent back over a channel, which the main loop
+// waits on, to the progress bar.
+// Menu actions that use the image are disabled while a job is running.
+
+use log::{error, info};
+use std::any::Any;
+use std::cell::RefCell;
+use std::io;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::thread;
+
+use gtk::{gio, glib, prelude::*};
+
+use hidey::steg::{StegError, Steganography};
+
+use crate::ui::dialogs::show_error;
+use crate::ui::{refresh_window, MainWindow};
+
+// Menu actions that use the image, disabled while a job is running.
+const IMAGE_ACTIONS: [&str; 7] = ["open", "open_recent", "save", "save_as", "embed", "extract", "preview"];
+
+// Messages from the worker thread.
+enum WorkerMessage<T> {
+    Progress { name: String, done: u64, total: u64 },
+    Done(Box<Steganography>, Result<T, StegError>),
+}
+
+// Run a job on a worker thread, then pass its result to on_done,
+// back on the main loop.
+pub fn run_job<T, J, D>(main_window: &MainWindow, img_steg: &Rc<RefCell<Steganography>>, title: &str, job: J, on_done: D)
+where
+    T: Send + 'static,
+    J: FnOnce(&mut Steganography) -> Result<T, StegError> + Send + 'static,
+    D: FnOnce(Result<T, StegError>) + 'static,
+{
+    if img_steg.borrow().img_proc_running {
+        info!("Job already running, not starting: {}", title);
+        return;
+    }
+    info!("Starting job: {}", title);
+
+    // Hand the instance over to the worker, leaving one behind
+    // that shows that a job is running.
+    let mut steg = {
+        let mut img_steg = img_steg.borrow_mut();
+        let mut running = Steganography::new(img_steg.settings.clone());
+        running.img_proc_running = true;
+        mem::replace(&mut *img_steg, running)
+    };
+    steg.img_proc_running = true;
+    steg.progress.cancel.reset();
+    *main_window.cancel.borrow_mut() = Some(steg.progress.cancel.clone());
+
+    let (sender, receiver) = async_channel::unbounded();
+    let progress_sender = sender.clone();
+    steg.progress.observer = Some(Box::new(move |name: &str, done: u64, total: u64| {
+        let _ = progress_sender.try_send(WorkerMessage::Progress { name: name.to_string(), done, total });
+    }));
+    let job_title = title.to_string();
+    thread::spawn(move || {
+        // A panicking job still hands the instance back, with an error,
+        // so that the loaded image isn't lost.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| job(&mut steg))).unwrap_or_else(|payload| {
+            let reason = panic_reason(payload.as_ref());
+            error!("Job panicked: {}, {}", job_title, reason);
+            Err(StegError::Io(io::Error::other(format!("Job stopped unexpectedly: {}", reason))))
+        });
+        steg.progress.observer = None;
+        steg.img_proc_running = false;
+        let _ = sender.try_send(WorkerMessage::Done(Box::new(steg), result));
+    });
+    set_job_running(main_window, true);
+    main_window.progress_bar.set_text(Some(title));
+
+    let main_window = main_window.clone();
+    let img_steg = img_steg.clone();
+    let title = title.to_string();
+    glib::MainContext::default().spawn_local(async move {
+        while let Ok(message) = receiver.recv().await {
+            match message {
+                WorkerMessage::Progress { name, done, total } => {
+                    let fraction = if total == 0 { 1.0 } else { done as f64 / total as f64 };
+                    main_window.progress_bar.set_fraction(fraction);
+                    main_window.progress_bar.set_text(Some(&format!("{}: {}", title, name)));
+                }
+                WorkerMessage::Done(steg, result) => {
+                    info!("Job finished: {}", title);
+                    *img_steg.borrow_mut() = *steg;
+                    set_job_running(&main_window, false);
+                    on_done(result);
+                    return;
+                }
+            }
+        }
+
+        // The worker went away without handing the image back.
+        error!("Job stopped unexpectedly: {}", title);
+        img_steg.borrow_mut().img_proc_running = false;
+        set_job_running(&main_window, false);
+        refresh_window(&main_window, &img_steg.borrow());
+        show_error(&main_window.window, "Job stopped unexpectedly", "Open the image again to carry on.");
+    });
+}
+
+// Why a job panicked, from the panic message if there is one.
+fn panic_reason(payload: &(dyn Any + Send)) -> String {
+    if let Some(reason) = payload.downcast_ref::<&str>() {
+        reason.to_string()
+    }
+    else if let Some(reason) = payload.downcast_ref::<String>() {
+        reason.clone()
+    }
+    else {
+        String::from("unknown reason")
+    }
+}
+
+// Show the progress bar, and disable the image menu actions,
+// while a job is running.
+fn set_job_running(main_window: &MainWindow, running: bool) {
+    if let Some(app) = main_window.window.application() {
+        for name in IMAGE_ACTIONS {
+            if let Some(action) = app.lookup_action(name).and_downcast::<gio::SimpleAction>() {
+                action.set_enabled(!running);
+            }
+        }
+    }
+    main_window.progress_bar.set_fraction(0.0);
+    main_window.progress_box.set_visible(running);
+    if !running {
+        *main_window.cancel.borrow_mut() = None;
+    }
+}